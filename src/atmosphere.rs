@@ -0,0 +1,175 @@
+use nalgebra_glm::Vec3;
+use crate::color::Color;
+use crate::framebuffer::{BlendMode, Framebuffer};
+use crate::sun::project;
+use crate::utils::{ray_sphere, smoothstep, unproject};
+use crate::Uniforms;
+
+// Capa de gas alrededor de un planeta, integrada a lo largo del rayo de vista
+#[derive(Debug, Clone, Copy)]
+pub struct Atmosphere {
+    // Dispersión por canal (tipo Rayleigh): tiñe el cielo y, por lo que le quita
+    // a la luz del sol, colorea los atardeceres con el tono complementario
+    pub scattering: Vec3,
+    // Dispersión gris hacia adelante (tipo Mie), da el brillo alrededor del sol
+    pub mie: f32,
+    pub mie_anisotropy: f32,
+    // Altura de la capa en radios del planeta
+    pub thickness: f32,
+    // Qué tan rápido cae la densidad con la altura
+    pub falloff: f32,
+    pub intensity: f32,
+}
+
+// Cielo azul con atardeceres rojizos
+pub const BLUE_ATMOSPHERE: Atmosphere = Atmosphere {
+    scattering: Vec3::new(0.25, 0.6, 1.4),
+    mie: 0.15,
+    mie_anisotropy: 0.75,
+    thickness: 0.12,
+    falloff: 4.0,
+    intensity: 2.2,
+};
+
+// Polvo en suspensión: cielo anaranjado y atardeceres azulados
+pub const DUSTY_ATMOSPHERE: Atmosphere = Atmosphere {
+    scattering: Vec3::new(1.1, 0.55, 0.2),
+    mie: 0.3,
+    mie_anisotropy: 0.65,
+    thickness: 0.08,
+    falloff: 5.0,
+    intensity: 1.6,
+};
+
+const VIEW_SAMPLES: usize = 10;
+
+impl Atmosphere {
+    // Luz dispersada hacia la cámara a lo largo del tramo (inicio, fin) del rayo
+    fn in_scattering(&self, origin: &Vec3, direction: &Vec3, segment: (f32, f32), center: &Vec3, planet_radius: f32, uniforms: &Uniforms) -> Vec3 {
+        let (start, end) = segment;
+        let height_scale = planet_radius * self.thickness;
+        let step = (end - start) / VIEW_SAMPLES as f32;
+        let extinction = self.scattering + Vec3::repeat(self.mie);
+
+        let mut view_depth = 0.0;
+        let mut rayleigh = Vec3::zeros();
+        let mut mie = Vec3::zeros();
+
+        for i in 0..VIEW_SAMPLES {
+            let point = origin + direction * (start + (i as f32 + 0.5) * step);
+            let up = (point - center) / (point - center).magnitude();
+            let height = (((point - center).magnitude() - planet_radius) / height_scale).clamp(0.0, 1.0);
+            let density = (-height * self.falloff).exp() * step / height_scale;
+            view_depth += density * 0.5;
+
+            for light in uniforms.lights {
+                let Some(to_light) = light.direction_from(&point) else {
+                    continue;
+                };
+
+                // Lado nocturno: se apaga suavemente pasando el terminador
+                let sun_height = up.dot(&to_light);
+                let daylight = smoothstep(-0.25, 0.1, sun_height);
+                if daylight <= 0.0 {
+                    continue;
+                }
+
+                // Camino de la luz del sol: más largo cuanto más rasante (masa de aire)
+                let air_mass = 1.0 / (sun_height.max(0.0) + 0.12);
+                let sun_depth = (-height * self.falloff).exp() * air_mass * 0.6;
+                let transmittance = (-extinction * (view_depth + sun_depth)).map(f32::exp);
+
+                let mu = direction.dot(&to_light);
+                let light_color = light.color * light.intensity * daylight;
+                let arrived = transmittance.component_mul(&light_color) * density;
+                rayleigh += arrived * rayleigh_phase(mu);
+                mie += arrived * henyey_greenstein(mu, self.mie_anisotropy);
+            }
+
+            view_depth += density * 0.5;
+        }
+
+        (rayleigh.component_mul(&self.scattering) + mie * self.mie) * self.intensity
+    }
+}
+
+fn rayleigh_phase(mu: f32) -> f32 {
+    0.75 * (1.0 + mu * mu)
+}
+
+fn henyey_greenstein(mu: f32, g: f32) -> f32 {
+    (1.0 - g * g) / (1.0 + g * g - 2.0 * g * mu).max(1e-4).powf(1.5)
+}
+
+// Suma la atmósfera sobre los pixeles que cubre la capa exterior del planeta
+pub fn draw_atmosphere(framebuffer: &mut Framebuffer, atmosphere: &Atmosphere, center: &Vec3, planet_radius: f32, uniforms: &Uniforms) {
+    let view_projection = uniforms.projection_matrix * uniforms.view_matrix;
+    let Some(inverse) = view_projection.try_inverse() else {
+        return;
+    };
+    let outer_radius = planet_radius * (1.0 + atmosphere.thickness);
+    let eye = uniforms.camera_position;
+
+    // Rectángulo de pantalla que contiene la esfera exterior (todo si la cámara está dentro)
+    let clip = framebuffer.clip_rect();
+    let (mut min_x, mut min_y) = (clip.x, clip.y);
+    let (mut max_x, mut max_y) = (clip.x + clip.width, clip.y + clip.height);
+    let distance = (center - eye).magnitude();
+    if distance > outer_radius * 1.01 {
+        let Some(center_screen) = project(center, uniforms) else {
+            return;
+        };
+        // Radio angular de la esfera vista desde la cámara, llevado a pixeles
+        let view = &uniforms.view_matrix;
+        let right = Vec3::new(view[(0, 0)], view[(0, 1)], view[(0, 2)]);
+        let angular = (outer_radius / distance).asin();
+        let edge = center + right * distance * angular.tan() * 1.05;
+        let Some(edge_screen) = project(&edge, uniforms) else {
+            return;
+        };
+        let reach = (edge_screen.xy() - center_screen.xy()).magnitude() + 1.0;
+        min_x = min_x.max((center_screen.x - reach).floor().max(0.0) as usize);
+        min_y = min_y.max((center_screen.y - reach).floor().max(0.0) as usize);
+        max_x = max_x.min((center_screen.x + reach).ceil().max(0.0) as usize);
+        max_y = max_y.min((center_screen.y + reach).ceil().max(0.0) as usize);
+    }
+
+    // Suma aditiva sin escribir profundidad, como la corona del sol
+    framebuffer.set_blend_mode(BlendMode::Additive);
+    framebuffer.set_depth_write(false);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let far = unproject(&inverse, x, y, framebuffer.width, framebuffer.height, 1.0);
+            let direction = (far - eye).normalize();
+
+            let Some((enter, exit)) = ray_sphere(&eye, &direction, center, outer_radius) else {
+                continue;
+            };
+            if exit <= 0.0 {
+                continue;
+            }
+            let start = enter.max(0.0);
+            // El rayo termina en la superficie si la toca
+            let end = match ray_sphere(&eye, &direction, center, planet_radius) {
+                Some((surface, _)) if surface > 0.0 => surface,
+                _ => exit,
+            };
+
+            let color = atmosphere.in_scattering(&eye, &direction, (start, end), center, planet_radius, uniforms);
+            if color.max() <= 1e-4 {
+                continue;
+            }
+
+            // Profundidad del borde de entrada para quedar detrás de lo que esté delante
+            let Some(entry) = project(&(eye + direction * start.max(0.1)), uniforms) else {
+                continue;
+            };
+            framebuffer.set_current_color(Color::from_vec3(color));
+            framebuffer.point(x, y, entry.z);
+        }
+    }
+
+    framebuffer.set_blend_mode(BlendMode::Replace);
+    framebuffer.set_depth_write(true);
+}
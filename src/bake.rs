@@ -0,0 +1,304 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use nalgebra_glm::{Vec2, Vec3};
+use crate::color::Color;
+use crate::displacement::VertexProgram;
+use crate::fragment::Fragment;
+use crate::material::{Material, Surface};
+use crate::noise::NoiseConfig;
+use crate::tonemap::linear_to_srgb;
+use crate::utils::{cube_face_direction, cube_locate};
+use crate::vertex::Vertex;
+use crate::Uniforms;
+
+// Cómo se guardan las direcciones de la esfera en la textura
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BakeLayout {
+    // Longitud en x y latitud en y, el doble de ancho que de alto
+    Equirectangular { width: usize },
+    // Seis caras cuadradas apiladas en vertical; sin estiramiento en los polos
+    CubeMap { size: usize },
+}
+
+impl BakeLayout {
+    // "equirect[:ancho]" o "cube[:lado]"
+    pub fn parse(value: &str) -> Option<Self> {
+        let (kind, resolution) = match value.split_once(':') {
+            Some((kind, resolution)) => (kind, Some(resolution.parse::<usize>().ok().filter(|&r| r >= 4)?)),
+            None => (value, None),
+        };
+        match kind {
+            "equirect" => Some(BakeLayout::Equirectangular { width: resolution.unwrap_or(1024) }),
+            "cube" => Some(BakeLayout::CubeMap { size: resolution.unwrap_or(256) }),
+            _ => None,
+        }
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        match *self {
+            BakeLayout::Equirectangular { width } => (width, (width / 2).max(1)),
+            BakeLayout::CubeMap { size } => (size, size * 6),
+        }
+    }
+
+    // Dirección que corresponde al centro de un texel
+    fn direction(&self, x: usize, y: usize) -> Vec3 {
+        let (width, height) = self.dimensions();
+        match *self {
+            BakeLayout::Equirectangular { .. } => {
+                let longitude = (x as f32 + 0.5) / width as f32 * 2.0 * PI - PI;
+                let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+                Vec3::new(latitude.cos() * longitude.sin(), latitude.sin(), latitude.cos() * longitude.cos())
+            }
+            BakeLayout::CubeMap { size } => {
+                let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let v = ((y % size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                cube_face_direction(y / size, u, v).normalize()
+            }
+        }
+    }
+}
+
+// Superficie de un material guardada por dirección
+pub struct SurfaceMap {
+    layout: BakeLayout,
+    width: usize,
+    height: usize,
+    texels: Vec<Surface>,
+}
+
+impl SurfaceMap {
+    // Evalúa la superficie sobre una esfera del radio dado, repartiendo las filas entre hilos.
+    // Con `relief` cada punto se desplaza como los vértices de la malla, para que el
+    // material vea la misma altura que al sombrear en vivo
+    pub fn bake(material: &dyn Material, noise: &NoiseConfig, radius: f32, relief: Option<&dyn VertexProgram>, layout: BakeLayout) -> Self {
+        let noise = noise.build();
+        let (width, height) = layout.dimensions();
+        let mut texels = vec![Surface::new(Color::black()); width * height];
+
+        let workers = thread::available_parallelism().map_or(4, |count| count.get());
+        let rows_per_worker = height.div_ceil(workers);
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in texels.chunks_mut(rows_per_worker * width).enumerate() {
+                let noise = &noise;
+                scope.spawn(move || {
+                    let uniforms = Uniforms::object_space(noise);
+                    let first = chunk_index * rows_per_worker * width;
+                    for (offset, texel) in chunk.iter_mut().enumerate() {
+                        let index = first + offset;
+                        let direction = layout.direction(index % width, index / width);
+                        let mut vertex = Vertex::new(direction * radius, direction, Vec2::zeros());
+                        if let Some(program) = relief {
+                            vertex = program.process(&vertex, &uniforms);
+                        }
+                        let fragment = Fragment::new(0.0, 0.0, 0.0, vertex.normal, 1.0, vertex.position, vertex.position);
+                        *texel = material.surface(&fragment, &uniforms);
+                    }
+                });
+            }
+        });
+
+        SurfaceMap { layout, width, height, texels }
+    }
+
+    // Filtrado bilineal; la longitud da la vuelta y las caras del cubo se recortan en el borde
+    pub fn sample(&self, position: &Vec3) -> Surface {
+        let direction = if position.magnitude_squared() > 0.0 { position.normalize() } else { Vec3::y() };
+        match self.layout {
+            BakeLayout::Equirectangular { .. } => {
+                let u = (direction.x.atan2(direction.z) + PI) / (2.0 * PI);
+                let v = 0.5 - direction.y.clamp(-1.0, 1.0).asin() / PI;
+                let fx = u * self.width as f32 - 0.5;
+                let fy = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+                let x0 = fx.floor();
+                let y0 = fy.floor() as usize;
+                let wrap = |x: f32| (x as isize).rem_euclid(self.width as isize) as usize;
+                self.bilinear((wrap(x0), wrap(x0 + 1.0)), (y0, (y0 + 1).min(self.height - 1)), (fx - x0, fy - y0 as f32))
+            }
+            BakeLayout::CubeMap { size } => {
+                let (face, u, v) = cube_locate(&direction);
+                let max = (size - 1) as f32;
+                let fx = ((u + 1.0) * 0.5 * size as f32 - 0.5).clamp(0.0, max);
+                let fy = ((v + 1.0) * 0.5 * size as f32 - 0.5).clamp(0.0, max);
+                let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+                let (x1, y1) = ((x0 + 1).min(size - 1), (y0 + 1).min(size - 1));
+                let row = face * size;
+                self.bilinear((x0, x1), (row + y0, row + y1), (fx - x0 as f32, fy - y0 as f32))
+            }
+        }
+    }
+
+    fn bilinear(&self, (x0, x1): (usize, usize), (y0, y1): (usize, usize), (tx, ty): (f32, f32)) -> Surface {
+        let texel = |x: usize, y: usize| &self.texels[y * self.width + x];
+        let top = texel(x0, y0).lerp(texel(x1, y0), tx);
+        let bottom = texel(x0, y1).lerp(texel(x1, y1), tx);
+        top.lerp(&bottom, ty)
+    }
+
+    // Guarda <prefijo>_albedo.png (sRGB con alfa), <prefijo>_masks.png (brillo en rojo,
+    // capa animada en verde), <prefijo>_emission.png y <prefijo>_night.png (recortadas a [0, 1])
+    pub fn save_png(&self, prefix: &str) -> Result<(), String> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        let encode = |c: f32| (linear_to_srgb(c) * 255.0 + 0.5) as u8;
+        let unit = |c: f32| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+
+        let mut albedo = image::RgbaImage::new(width, height);
+        let mut masks = image::RgbImage::new(width, height);
+        let mut emission = image::RgbImage::new(width, height);
+        let mut night = image::RgbImage::new(width, height);
+        for (i, texel) in self.texels.iter().enumerate() {
+            let (x, y) = ((i % self.width) as u32, (i / self.width) as u32);
+            let color = texel.albedo.to_vec3();
+            albedo.put_pixel(x, y, image::Rgba([encode(color.x), encode(color.y), encode(color.z), unit(texel.albedo.alpha())]));
+            masks.put_pixel(x, y, image::Rgb([unit(texel.specular_mask), unit(texel.layer), 0]));
+            emission.put_pixel(x, y, image::Rgb([encode(texel.emission.x), encode(texel.emission.y), encode(texel.emission.z)]));
+            night.put_pixel(x, y, image::Rgb([encode(texel.night.x), encode(texel.night.y), encode(texel.night.z)]));
+        }
+
+        let save = |suffix: &str, result: image::ImageResult<()>| result.map_err(|e| format!("{}_{}.png: {}", prefix, suffix, e));
+        save("albedo", albedo.save(format!("{}_albedo.png", prefix)))?;
+        save("masks", masks.save(format!("{}_masks.png", prefix)))?;
+        save("emission", emission.save(format!("{}_emission.png", prefix)))?;
+        save("night", night.save(format!("{}_night.png", prefix)))
+    }
+}
+
+// Material que sombrea muestreando su superficie horneada; la iluminación y las
+// capas animadas siguen siendo las del material original
+pub struct BakedMaterial {
+    source: Arc<dyn Material>,
+    map: Arc<OnceLock<SurfaceMap>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BakedMaterial {
+    // Hornea en un hilo aparte; mientras tanto la superficie se calcula como siempre
+    pub fn spawn(source: Arc<dyn Material>, noise: NoiseConfig, radius: f32, relief: Option<Arc<dyn VertexProgram>>, layout: BakeLayout) -> Self {
+        let map = Arc::new(OnceLock::new());
+        let worker = {
+            let (source, map) = (source.clone(), map.clone());
+            thread::spawn(move || {
+                let _ = map.set(SurfaceMap::bake(source.as_ref(), &noise, radius, relief.as_deref(), layout));
+            })
+        };
+        BakedMaterial { source, map, worker: Mutex::new(Some(worker)) }
+    }
+
+    #[cfg(test)]
+    pub fn map(&self) -> Option<&SurfaceMap> {
+        self.map.get()
+    }
+
+    // Espera a que termine el horneado; None si el hilo falló
+    pub fn wait(&self) -> Option<&SurfaceMap> {
+        let worker = self.worker.lock().ok().and_then(|mut worker| worker.take());
+        if let Some(worker) = worker {
+            let _ = worker.join();
+        }
+        self.map.get()
+    }
+}
+
+impl Material for BakedMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        match self.map.get() {
+            Some(map) => map.sample(&fragment.vertex_position),
+            None => self.source.surface(fragment, uniforms),
+        }
+    }
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        self.source.light(surface, fragment, uniforms)
+    }
+
+    // Ya está horneado
+    fn bakeable(&self) -> bool {
+        false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displacement::{Displacement, HeightField};
+    use crate::noise::Noise;
+
+    // La superficie guarda su propia dirección como color
+    struct Directions;
+
+    impl Material for Directions {
+        fn surface(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Surface {
+            let direction = fragment.vertex_position.normalize();
+            Surface::new(Color::linear(direction.x + 1.0, direction.y + 1.0, direction.z + 1.0))
+        }
+
+        fn light(&self, surface: &Surface, _fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            surface.albedo
+        }
+    }
+
+    #[test]
+    fn sampling_returns_the_baked_direction() {
+        for layout in [BakeLayout::Equirectangular { width: 128 }, BakeLayout::CubeMap { size: 32 }] {
+            let map = SurfaceMap::bake(&Directions, &NoiseConfig::default(), 0.5, None, layout);
+            for i in 0..50 {
+                let angle = i as f32 * 0.9;
+                let direction = Vec3::new(angle.cos(), (i as f32 * 0.37).sin() * 0.9, angle.sin()).normalize();
+                let sampled = map.sample(&(direction * 0.5)).albedo.to_vec3() - Vec3::repeat(1.0);
+                assert!((sampled - direction).magnitude() < 0.08, "{:?}: {:?} != {:?}", layout, sampled, direction);
+            }
+        }
+    }
+
+    // Guarda a qué distancia del centro se evaluó la superficie
+    struct Radius;
+
+    impl Material for Radius {
+        fn surface(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Surface {
+            let radius = fragment.vertex_position.magnitude();
+            Surface::new(Color::linear(radius, radius, radius))
+        }
+
+        fn light(&self, surface: &Surface, _fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            surface.albedo
+        }
+    }
+
+    // Relieve que crece con x, para que la altura cambie según la dirección
+    struct Slope;
+
+    impl HeightField for Slope {
+        fn height(&self, point: &Vec3, _noise: &Noise) -> f32 {
+            (point.x + 0.5) * 0.1
+        }
+    }
+
+    #[test]
+    fn relief_is_baked_at_the_displaced_height() {
+        let relief = Displacement::new(Slope);
+        let map = SurfaceMap::bake(&Radius, &NoiseConfig::default(), 0.5, Some(&relief), BakeLayout::CubeMap { size: 32 });
+        for direction in [Vec3::x(), -Vec3::x(), Vec3::y(), Vec3::new(0.3, -0.4, 0.8).normalize()] {
+            let expected = 0.5 + (direction.x * 0.5 + 0.5) * 0.1;
+            let sampled = map.sample(&direction).albedo.to_vec3().x;
+            assert!((sampled - expected).abs() < 2e-3, "{:?}: {} != {}", direction, sampled, expected);
+        }
+    }
+
+    #[test]
+    fn baked_material_switches_to_the_map_when_ready() {
+        let baked = BakedMaterial::spawn(Arc::new(Directions), NoiseConfig::default(), 0.5, None, BakeLayout::CubeMap { size: 8 });
+        assert!(baked.wait().is_some());
+        assert!(baked.map().is_some());
+        assert!(!baked.bakeable());
+    }
+
+    #[test]
+    fn layouts_parse_with_optional_resolution() {
+        assert_eq!(BakeLayout::parse("equirect"), Some(BakeLayout::Equirectangular { width: 1024 }));
+        assert_eq!(BakeLayout::parse("cube:128"), Some(BakeLayout::CubeMap { size: 128 }));
+        assert_eq!(BakeLayout::parse("cube:x"), None);
+        assert_eq!(BakeLayout::parse("sphere"), None);
+    }
+}
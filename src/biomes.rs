@@ -0,0 +1,304 @@
+use nalgebra_glm::Vec3;
+use crate::color::Color;
+use crate::fragment::Fragment;
+use crate::light::{blinn_phong, Specular};
+use crate::material::{Material, Surface};
+use crate::noise::Fractal;
+use crate::ocean::Ocean;
+use crate::shaders::{Islands, WATER_SPECULAR};
+use crate::utils::smoothstep;
+use crate::Uniforms;
+
+// Clima de un punto: elevación sobre el mar (negativa en el agua), temperatura en
+// [0, 1] (1 en el ecuador al nivel del mar) y humedad en [0, 1]
+#[derive(Debug, Clone, Copy)]
+pub struct Climate {
+    pub elevation: f32,
+    pub temperature: f32,
+    pub moisture: f32,
+}
+
+// Una fila de la tabla: dónde aparece el bioma y cómo se ve
+#[derive(Debug, Clone)]
+pub struct Biome {
+    pub name: String,
+    pub color: Color,
+    // Rangos (mínimo, máximo); sin límite por defecto
+    pub temperature: (f32, f32),
+    pub moisture: (f32, f32),
+    pub elevation: (f32, f32),
+    // Máscara del reflejo del sol y fracción de las ciudades que se ven de noche
+    pub specular: f32,
+    pub cities: f32,
+    // Cuánto de la superficie es agua abierta, con olas y reflejos
+    pub water: f32,
+}
+
+impl Biome {
+    pub fn new(name: &str, color: Color) -> Self {
+        let everywhere = (f32::NEG_INFINITY, f32::INFINITY);
+        Biome {
+            name: name.to_string(),
+            color,
+            temperature: everywhere,
+            moisture: everywhere,
+            elevation: everywhere,
+            specular: 0.05,
+            cities: 1.0,
+            water: 0.0,
+        }
+    }
+
+    // Cuánto cae el clima dentro de los tres rangos, con bordes del ancho dado
+    fn coverage(&self, climate: &Climate, blend: &(f32, f32, f32)) -> f32 {
+        within(self.temperature, climate.temperature, blend.0)
+            * within(self.moisture, climate.moisture, blend.1)
+            * within(self.elevation, climate.elevation, blend.2)
+    }
+}
+
+// Mezcla de biomas en un punto
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeMix {
+    pub color: Color,
+    pub specular: f32,
+    pub cities: f32,
+    pub water: f32,
+}
+
+// Tabla ordenada por prioridad: el primer bioma que coincide gana y los bordes
+// suaves dejan ver al siguiente; el último cubre lo que sobre
+#[derive(Debug, Clone)]
+pub struct BiomeTable {
+    pub biomes: Vec<Biome>,
+    // Ancho de las transiciones en temperatura, humedad y elevación
+    pub blend: (f32, f32, f32),
+}
+
+impl BiomeTable {
+    pub fn new(biomes: Vec<Biome>) -> Self {
+        BiomeTable { biomes, blend: (0.04, 0.06, 0.004) }
+    }
+
+    pub fn lookup(&self, climate: &Climate) -> BiomeMix {
+        let mut color = Vec3::zeros();
+        let (mut specular, mut cities, mut water) = (0.0, 0.0, 0.0);
+        let mut remaining = 1.0;
+        for (i, biome) in self.biomes.iter().enumerate() {
+            let coverage = if i + 1 == self.biomes.len() { 1.0 } else { biome.coverage(climate, &self.blend) };
+            let weight = remaining * coverage;
+            color += biome.color.to_vec3() * weight;
+            specular += biome.specular * weight;
+            cities += biome.cities * weight;
+            water += biome.water * weight;
+            remaining -= weight;
+            if remaining <= 1e-4 {
+                break;
+            }
+        }
+        BiomeMix { color: Color::from_vec3(color), specular, cities, water }
+    }
+
+    // Una línea por bioma:
+    //
+    //   <nombre> <r> <g> <b> [temperature <mín> <máx>] [moisture <mín> <máx>]
+    //                        [elevation <mín> <máx>] [specular <máscara>] [cities <fracción>]
+    //                        [water <fracción>]
+    //   blend <temperatura> <humedad> <elevación>
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut table = BiomeTable::new(Vec::new());
+        for (number, line) in source.lines().enumerate() {
+            let words = line.split('#').next().unwrap_or("").split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                [] => {}
+                ["blend", temperature, moisture, elevation] => {
+                    table.blend = (number_at(temperature, number)?, number_at(moisture, number)?, number_at(elevation, number)?);
+                }
+                [name, r, g, b, options @ ..] => {
+                    let channel = |word: &str| word.parse::<u8>().map_err(|_| format!("línea {}: canal inválido '{}'", number + 1, word));
+                    let mut biome = Biome::new(name, Color::new(channel(r)?, channel(g)?, channel(b)?));
+                    let mut options = options.iter();
+                    while let Some(option) = options.next() {
+                        let mut value = || number_at(options.next().unwrap_or(&""), number);
+                        match *option {
+                            "temperature" => biome.temperature = (value()?, value()?),
+                            "moisture" => biome.moisture = (value()?, value()?),
+                            "elevation" => biome.elevation = (value()?, value()?),
+                            "specular" => biome.specular = value()?,
+                            "cities" => biome.cities = value()?,
+                            "water" => biome.water = value()?,
+                            other => return Err(format!("línea {}: opción desconocida '{}'", number + 1, other)),
+                        }
+                    }
+                    if table.biomes.iter().any(|other| other.name == biome.name) {
+                        return Err(format!("línea {}: bioma repetido '{}'", number + 1, biome.name));
+                    }
+                    table.biomes.push(biome);
+                }
+                _ => return Err(format!("línea {}: se esperaba '<bioma> <r> <g> <b> ...'", number + 1)),
+            }
+        }
+
+        if table.biomes.is_empty() {
+            return Err("la tabla no tiene biomas".to_string());
+        }
+        Ok(table)
+    }
+}
+
+// Planeta tipo Tierra: elevación, temperatura y humedad eligen el bioma de la tabla.
+// La latitud se mide sobre el eje y del modelo, así que los casquetes siguen la
+// inclinación del eje de giro que se le dé al cuerpo
+pub struct BiomeMaterial {
+    pub table: BiomeTable,
+    // Relieve y ciudades como los del planeta habitable; sus colores no se usan
+    pub islands: Islands,
+    // Fbm de la humedad y del ruido que tuerce las isotermas
+    pub moisture_fractal: Fractal,
+    pub temperature_fractal: Fractal,
+    // Cuánto enfría cada unidad de elevación y cuánto ruido tuercen las isotermas
+    pub lapse_rate: f32,
+    pub temperature_jitter: f32,
+    // Sequedad de la franja subtropical (seno de la latitud ~0.45), donde bajan los alisios
+    pub desert_belt: f32,
+    // Fuerza del relieve de la tierra firme en el bump
+    pub relief: f32,
+    pub specular: Specular,
+    // Luz del agua de los biomas marcados con `water`
+    pub ocean: Ocean,
+}
+
+impl BiomeMaterial {
+    pub fn new(table: BiomeTable) -> Self {
+        BiomeMaterial {
+            table,
+            islands: Islands { sea_level: 0.05, ..Islands::default() },
+            moisture_fractal: Fractal::new(90.0, 4, 2.0, 0.5),
+            temperature_fractal: Fractal::new(60.0, 3, 2.0, 0.5),
+            lapse_rate: 1.2,
+            temperature_jitter: 0.08,
+            desert_belt: 0.3,
+            relief: 0.05,
+            specular: WATER_SPECULAR,
+            ocean: Ocean::default(),
+        }
+    }
+
+    pub fn climate(&self, uniforms: &Uniforms, position: &Vec3) -> Climate {
+        let elevation = self.islands.elevation(uniforms, position);
+        let latitude = position.normalize().y;
+
+        // cos² de la latitud, más frío en la altura; el mar no se enfría con la profundidad
+        let jitter = uniforms.noise.fractal(&(position + Vec3::new(-4.3, 2.9, 1.7)), self.temperature_fractal, |n| n);
+        let temperature = 1.0 - latitude * latitude - elevation.max(0.0) * self.lapse_rate + jitter * self.temperature_jitter;
+
+        let wet = uniforms.noise.fractal(&(position + Vec3::new(3.7, -1.3, 5.1)), self.moisture_fractal, |n| n);
+        let belt = (-((latitude.abs() - 0.45) / 0.15).powi(2)).exp();
+        let moisture = smoothstep(-0.4, 0.4, wet) - belt * self.desert_belt;
+
+        Climate { elevation, temperature, moisture: moisture.clamp(0.0, 1.0) }
+    }
+}
+
+impl Material for BiomeMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
+        let climate = self.climate(uniforms, &position);
+        let biome = self.table.lookup(&climate);
+
+        let night = self.islands.cities.emission(&position, climate.elevation, uniforms.noise) * biome.cities;
+        Surface::new(biome.color)
+            .with_specular_mask(biome.specular)
+            .with_night(night)
+            .with_layer(biome.water)
+    }
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        // Solo la tierra firme tiene relieve; el agua toma la normal de las olas
+        let land = || {
+            let normal = fragment.bump_normal(self.relief, |point| self.islands.elevation(uniforms, point).max(0.0));
+            blinn_phong(fragment, &normal, uniforms.lights, &self.specular).apply(surface.albedo, surface.specular_mask)
+        };
+        let lit_color = if surface.layer > 0.0 {
+            let depth = -self.islands.elevation(uniforms, &fragment.vertex_position);
+            let water = self.ocean.light(surface.albedo, depth, surface.specular_mask, fragment, uniforms);
+            if surface.layer >= 1.0 { water } else { land().lerp(&water, surface.layer) }
+        } else {
+            land()
+        };
+        lit_color + self.islands.night_lights(fragment, surface.night)
+    }
+}
+
+// 1 dentro del rango y 0 fuera, con una rampa del ancho dado centrada en cada límite
+fn within(range: (f32, f32), value: f32, width: f32) -> f32 {
+    let above = if range.0.is_finite() { smoothstep(range.0 - width, range.0 + width, value) } else { 1.0 };
+    let below = if range.1.is_finite() { 1.0 - smoothstep(range.1 - width, range.1 + width, value) } else { 1.0 };
+    above * below
+}
+
+fn number_at(word: &str, line: usize) -> Result<f32, String> {
+    word.parse::<f32>().map_err(|_| format!("línea {}: número inválido '{}'", line + 1, word))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::NoiseConfig;
+
+    const TABLE: &str = "
+        ice     240 240 240  temperature -1 0.1  specular 0.3 cities 0
+        ocean    20  60 140  elevation -1 0      specular 1
+        desert  210 180 120  moisture -1 0.3
+        forest   30  80  40
+    ";
+
+    fn climate(elevation: f32, temperature: f32, moisture: f32) -> Climate {
+        Climate { elevation, temperature, moisture }
+    }
+
+    #[test]
+    fn first_matching_biome_wins_with_soft_edges() {
+        let table = BiomeTable::parse(TABLE).unwrap();
+        let color = |c: &Climate| table.lookup(c).color.to_vec3();
+        let named = |name: &str| table.biomes.iter().find(|b| b.name == name).unwrap().color.to_vec3();
+
+        // El hielo va primero en la tabla, así que cubre también el mar polar
+        assert!((color(&climate(-0.2, 0.0, 0.5)) - named("ice")).magnitude() < 1e-4);
+        assert!((color(&climate(-0.2, 0.6, 0.5)) - named("ocean")).magnitude() < 1e-4);
+        assert!((color(&climate(0.2, 0.6, 0.1)) - named("desert")).magnitude() < 1e-4);
+        // Lo que no entra en ninguno queda para el último
+        assert!((color(&climate(0.2, 0.6, 0.8)) - named("forest")).magnitude() < 1e-4);
+        assert_eq!(table.lookup(&climate(-0.2, 0.0, 0.5)).cities, 0.0);
+
+        // Sobre el límite de humedad los dos biomas se mezclan por partes iguales
+        let edge = color(&climate(0.2, 0.6, 0.3));
+        assert!((edge - (named("desert") + named("forest")) * 0.5).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn ice_caps_cover_the_poles() {
+        let material = BiomeMaterial::new(BiomeTable::parse(TABLE).unwrap());
+        let noise = NoiseConfig::new(53).build();
+        let uniforms = Uniforms::object_space(&noise);
+        let ice = material.table.biomes[0].color.to_vec3();
+
+        for pole in [Vec3::y(), -Vec3::y()] {
+            let fragment = Fragment::new(0.0, 0.0, 0.0, pole, 1.0, pole, pole);
+            let surface = material.surface(&fragment, &uniforms);
+            assert!((surface.albedo.to_vec3() - ice).magnitude() < 1e-4);
+        }
+        let equator = Vec3::x();
+        assert!(material.climate(&uniforms, &equator).temperature > 0.5);
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let error = BiomeTable::parse("ice 240 240 240\nsand 1 2 3 wetness 0 1").unwrap_err();
+        assert!(error.starts_with("línea 2"), "{}", error);
+        assert!(BiomeTable::parse("ice 240 240").is_err());
+        assert!(BiomeTable::parse("ice 240 240 240\nice 1 2 3").unwrap_err().contains("repetido"));
+        assert!(BiomeTable::parse("# vacía").is_err());
+    }
+}
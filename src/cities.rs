@@ -0,0 +1,88 @@
+use nalgebra_glm::Vec3;
+use crate::noise::Noise;
+
+// Luces de ciudades para el lado nocturno: se agrupan en la costa, donde la
+// elevación cruza el nivel del mar, y quedan unas pocas tierra adentro
+#[derive(Debug, Clone, Copy)]
+pub struct CityLights {
+    // Color lineal y brillo en HDR, suficiente para que el bloom las agrande
+    pub color: Vec3,
+    pub intensity: f32,
+    // Hasta qué elevación sobre el mar llega la franja costera
+    pub coast_width: f32,
+    // Probabilidad relativa de ciudades lejos de la costa
+    pub inland: f32,
+    // Tamaño de las manchas urbanas y de los puntos de luz dentro de ellas
+    pub cluster_scale: f32,
+    pub sparkle_scale: f32,
+    // Ancho del terminador (en coseno) en el que se encienden
+    pub terminator: f32,
+}
+
+impl Default for CityLights {
+    fn default() -> Self {
+        CityLights {
+            color: Vec3::new(1.0, 0.72, 0.38),
+            intensity: 3.0,
+            coast_width: 0.08,
+            inland: 0.2,
+            cluster_scale: 2200.0,
+            sparkle_scale: 6000.0,
+            terminator: 0.15,
+        }
+    }
+}
+
+impl CityLights {
+    // Luz en un punto del modelo con la elevación dada (negativa bajo el mar)
+    pub fn emission(&self, position: &Vec3, elevation: f32, noise: &Noise) -> Vec3 {
+        if elevation <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        // Más ciudades cuanto más cerca de la costa
+        let coast = 1.0 - (elevation / self.coast_width).clamp(0.0, 1.0);
+        let presence = self.inland + (1.0 - self.inland) * coast * coast;
+
+        // Manchas urbanas: solo los picos del ruido, más anchos donde hay más presencia
+        let c = position * self.cluster_scale;
+        let cluster = noise.get_noise_3d(c.x, c.y, c.z) * 0.5 + 0.5;
+        let threshold = 1.0 - presence * 0.45;
+        let urban = ((cluster - threshold) / 0.08).clamp(0.0, 1.0);
+
+        // Calles y barrios: puntos más finos dentro de cada mancha
+        let s = position * self.sparkle_scale;
+        let sparkle = (noise.get_noise_3d(s.x + 311.0, s.y, s.z) * 0.5 + 0.5).powi(3);
+
+        self.color * (self.intensity * urban * (0.35 + 0.65 * sparkle))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::NoiseConfig;
+    use crate::utils::tests::sphere_samples;
+
+    // Fracción de puntos iluminados a una elevación fija
+    fn lit_fraction(lights: &CityLights, elevation: f32, noise: &Noise) -> f32 {
+        let samples = sphere_samples(2000);
+        let lit = samples.iter()
+            .filter(|direction| lights.emission(&(*direction * 0.5), elevation, noise).magnitude() > 0.0)
+            .count();
+        lit as f32 / samples.len() as f32
+    }
+
+    #[test]
+    fn lights_cluster_along_the_coast() {
+        let lights = CityLights::default();
+        let noise = NoiseConfig::new(53).build();
+
+        assert_eq!(lit_fraction(&lights, -0.01, &noise), 0.0);
+        let coast = lit_fraction(&lights, 0.005, &noise);
+        let inland = lit_fraction(&lights, 0.5, &noise);
+        assert!(coast > inland * 2.0, "costa {} tierra adentro {}", coast, inland);
+        assert!(inland > 0.0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+use nalgebra_glm::{Vec3, mat4_to_mat3};
+use crate::color::Color;
+use crate::fragment::Fragment;
+use crate::light::{blinn_phong, Specular};
+use crate::material::{Material, Surface};
+use crate::noise::{Fractal, Noise};
+use crate::utils::smoothstep;
+use crate::Uniforms;
+
+// Capa de nubes para una esfera un poco más grande que el planeta. La densidad
+// depende solo de la dirección, así que la superficie de abajo puede consultarla
+// con el mismo ruido para saber dónde le tapan el sol
+pub struct CloudMaterial {
+    pub color: Color,
+    // Altura de la capa como fracción del radio del planeta
+    pub altitude: f32,
+    // Fbm sobre la esfera unitaria
+    pub fractal: Fractal,
+    pub warp_scale: f32,
+    pub warp_amount: f32,
+    // Qué tan rápido se deforman las nubes además de moverse con el viento
+    pub evolution: f32,
+    // Fracción aproximada del cielo cubierta y ancho del borde de las nubes
+    pub coverage: f32,
+    pub softness: f32,
+    pub opacity: f32,
+    // Velocidad angular del viento (radianes por segundo, positiva hacia el este)
+    // repartida del ecuador a los polos e interpolada entre medio
+    pub winds: Vec<f32>,
+}
+
+impl Default for CloudMaterial {
+    // Alisios hacia el oeste en el ecuador, vientos del oeste en latitudes medias y
+    // polares otra vez hacia el oeste
+    fn default() -> Self {
+        CloudMaterial {
+            color: Color::linear(0.95, 0.96, 1.0),
+            altitude: 0.03,
+            fractal: Fractal::new(180.0, 5, 2.1, 0.5),
+            warp_scale: 120.0,
+            warp_amount: 0.12,
+            evolution: 0.015,
+            coverage: 0.45,
+            softness: 0.12,
+            opacity: 0.9,
+            winds: vec![-0.02, 0.0, 0.03, 0.015, -0.01],
+        }
+    }
+}
+
+impl CloudMaterial {
+    // Velocidad del viento para |seno de la latitud| en [0, 1]
+    fn wind(&self, latitude: f32) -> f32 {
+        match self.winds.len() {
+            0 => 0.0,
+            1 => self.winds[0],
+            len => {
+                let t = latitude.clamp(0.0, 1.0) * (len - 1) as f32;
+                let index = (t as usize).min(len - 2);
+                let f = smoothstep(0.0, 1.0, t - index as f32);
+                self.winds[index] + (self.winds[index + 1] - self.winds[index]) * f
+            }
+        }
+    }
+
+    // Densidad en [0, 1] para una dirección en espacio de objeto y un instante
+    pub fn density(&self, direction: &Vec3, noise: &Noise, time: f32) -> f32 {
+        let direction = direction.normalize();
+
+        // Cada latitud gira alrededor del eje a su propia velocidad
+        let angle = -self.wind(direction.y.abs()) * time;
+        let (sin, cos) = angle.sin_cos();
+        let advected = Vec3::new(direction.x * cos + direction.z * sin, direction.y, direction.z * cos - direction.x * sin);
+
+        // Deformación que avanza con el tiempo para que las formas cambien
+        let drift = time * self.evolution;
+        let w = advected * self.warp_scale;
+        let warp = Vec3::new(
+            noise.get_noise_3d(w.x + drift * 100.0, w.y, w.z),
+            noise.get_noise_3d(w.x, w.y - drift * 100.0, w.z + 57.1),
+            noise.get_noise_3d(w.x - 91.3, w.y, w.z + drift * 100.0),
+        );
+        let value = noise.fractal(&(advected + warp * self.warp_amount), self.fractal, |n| n) * 0.5 + 0.5;
+
+        let threshold = 1.0 - self.coverage;
+        smoothstep(threshold - self.softness, threshold + self.softness, value)
+    }
+}
+
+impl Material for CloudMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let density = self.density(&fragment.vertex_position, uniforms.noise, uniforms.time);
+        // Las nubes densas se ven más grises por debajo
+        let color = self.color * (1.0 - density * 0.25);
+        Surface::new(color.with_alpha(density * self.opacity)).with_specular_mask(0.0)
+    }
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        blinn_phong(fragment, &fragment.normal, uniforms.lights, &Specular::matte()).apply(surface.albedo, surface.specular_mask)
+    }
+
+    // Se mueven con el reloj
+    fn bakeable(&self) -> bool {
+        false
+    }
+}
+
+// Superficie bajo una capa de nubes: donde las nubes tapan el sol la luz principal
+// se atenúa, como una sombra más. La sombra se busca en la capa siguiendo el rayo
+// hacia el sol y se promedia en un disco para que el borde quede suave
+pub struct Overcast {
+    pub surface: Arc<dyn Material>,
+    pub clouds: Arc<CloudMaterial>,
+    // Cuánta luz quitan las nubes más densas
+    pub strength: f32,
+    // Radio del disco de muestras sobre la capa, en unidades de la esfera unitaria
+    pub penumbra: f32,
+}
+
+impl Overcast {
+    pub fn new(surface: Arc<dyn Material>, clouds: Arc<CloudMaterial>) -> Self {
+        Overcast { surface, clouds, strength: 0.7, penumbra: 0.02 }
+    }
+
+    // Fracción de la luz principal que pasa entre las nubes
+    fn transmittance(&self, fragment: &Fragment, uniforms: &Uniforms) -> f32 {
+        let direction = fragment.vertex_position.normalize();
+        // La matriz del modelo solo rota y escala, así que la transpuesta lleva la luz al objeto
+        let light = (mat4_to_mat3(&uniforms.model_matrix).transpose() * fragment.light_dir).normalize();
+        let facing = direction.dot(&light);
+        if facing <= 0.0 {
+            return 1.0;
+        }
+
+        // Donde el rayo hacia el sol cruza la capa
+        let radius = 1.0 + self.clouds.altitude;
+        let distance = -facing + (facing * facing - 1.0 + radius * radius).sqrt();
+        let above = direction + light * distance;
+
+        let (tangent, bitangent) = (fragment.vertex_tangent, fragment.vertex_bitangent);
+        let taps = [Vec3::zeros(), tangent, -tangent, bitangent, -bitangent];
+        let density = taps.iter()
+            .map(|offset| self.clouds.density(&(above + offset * self.penumbra), uniforms.noise, uniforms.time))
+            .sum::<f32>() / taps.len() as f32;
+
+        1.0 - density * self.clouds.opacity * self.strength
+    }
+}
+
+impl Material for Overcast {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        self.surface.surface(fragment, uniforms)
+    }
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let mut shaded = fragment.clone();
+        shaded.cloud_shadow *= self.transmittance(fragment, uniforms);
+        self.surface.light(surface, &shaded, uniforms)
+    }
+
+    // Las nubes solo cambian la luz; la superficie se hornea igual que antes
+    fn bakeable(&self) -> bool {
+        self.surface.bakeable()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::night_side;
+    use crate::noise::NoiseConfig;
+
+    // Devuelve la luz principal que llega y qué tan de noche es, como color
+    struct ShadowProbe;
+
+    impl Material for ShadowProbe {
+        fn surface(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> Surface {
+            Surface::new(Color::black())
+        }
+
+        fn light(&self, _surface: &Surface, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+            Color::linear(fragment.sunlight(), night_side(fragment, 0.1), 0.0)
+        }
+    }
+
+    fn shadow_under(coverage: f32, uniforms: &Uniforms) -> Vec3 {
+        let clouds = Arc::new(CloudMaterial { coverage, softness: 0.01, ..CloudMaterial::default() });
+        let overcast = Overcast::new(Arc::new(ShadowProbe), clouds);
+        let point = Vec3::new(0.0, 0.0, 0.5);
+        let mut fragment = Fragment::new(0.0, 0.0, 0.0, Vec3::z(), 1.0, point, point);
+        fragment.light_dir = Vec3::new(0.3, 0.0, 1.0).normalize();
+        overcast.shade(&fragment, uniforms).to_vec3()
+    }
+
+    #[test]
+    fn clouds_shade_the_surface_below() {
+        let noise = NoiseConfig::new(53).build();
+        let uniforms = Uniforms::object_space(&noise);
+        assert!((shadow_under(0.0, &uniforms).x - 1.0).abs() < 1e-4);
+        assert!(shadow_under(1.5, &uniforms).x < 0.5);
+    }
+
+    #[test]
+    fn clouds_do_not_turn_day_into_night() {
+        let noise = NoiseConfig::new(53).build();
+        let uniforms = Uniforms::object_space(&noise);
+        assert_eq!(shadow_under(1.5, &uniforms).y, 0.0);
+    }
+
+    #[test]
+    fn each_latitude_drifts_with_its_own_wind() {
+        let clouds = CloudMaterial { winds: vec![0.0, 0.0, 0.5], evolution: 0.0, softness: 0.5, ..CloudMaterial::default() };
+        let noise = NoiseConfig::new(53).build();
+        // Cambio total de densidad en un anillo a la altura dada entre dos instantes
+        let drift = |height: f32| (0..40)
+            .map(|i| {
+                let angle = i as f32 * 0.3;
+                let direction = Vec3::new(angle.cos(), height, angle.sin());
+                (clouds.density(&direction, &noise, 0.0) - clouds.density(&direction, &noise, 3.0)).abs()
+            })
+            .sum::<f32>();
+
+        // Sin viento en el ecuador el patrón se queda quieto; cerca del polo se mueve
+        assert!(drift(0.0) < 1e-4);
+        assert!(drift(3.0) > 0.1);
+    }
+}
@@ -1,54 +1,98 @@
 use std::fmt;
+use std::sync::OnceLock;
+use nalgebra_glm::Vec3;
+use crate::tonemap::{linear_to_srgb, srgb_to_linear};
 
+// Linear RGB color with alpha. Components are not clamped, so shaders can
+// return HDR values above 1.0; the framebuffer tone maps them at the end.
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+// sRGB 8-bit to linear, precomputed since shaders build colors per fragment
+fn decode_srgb(c: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)))[c as usize]
 }
 
 impl Color {
-    pub const fn new(r: u8, g: u8, b: u8) -> Self {
-        Color { r, g, b }
+    // 8-bit sRGB components, as picked in an image editor
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r: decode_srgb(r), g: decode_srgb(g), b: decode_srgb(b), a: 1.0 }
+    }
+
+    pub fn new_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color::new(r, g, b).with_alpha(a as f32 / 255.0)
+    }
+
+    // Linear components, may exceed 1.0
+    pub const fn linear(r: f32, g: f32, b: f32) -> Self {
+        Color { r, g, b, a: 1.0 }
     }
 
-    pub const fn from_hex(hex: u32) -> Self {
+    pub fn from_hex(hex: u32) -> Self {
         let r = ((hex >> 16) & 0xFF) as u8;
         let g = ((hex >> 8) & 0xFF) as u8;
         let b = (hex & 0xFF) as u8;
-        Color { r, g, b }
+        Color::new(r, g, b)
+    }
+
+    // sRGB components in [0, 1]
+    pub fn from_srgb_vec3(v: Vec3) -> Self {
+        Color::linear(srgb_to_linear(v.x.max(0.0)), srgb_to_linear(v.y.max(0.0)), srgb_to_linear(v.z.max(0.0)))
     }
 
     pub const fn black() -> Self {
-        Color { r: 0, g: 0, b: 0 }
+        Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }
+    }
+
+    // Clamped and sRGB encoded; alpha is not part of the hex value
+    pub fn to_hex(self) -> u32 {
+        let encode = |c: f32| (linear_to_srgb(c) * 255.0 + 0.5) as u32;
+        (encode(self.r) << 16) | (encode(self.g) << 8) | encode(self.b)
+    }
+
+    // Opacity in [0, 1]
+    pub fn alpha(self) -> f32 {
+        self.a
+    }
+
+    pub fn with_alpha(self, alpha: f32) -> Self {
+        Color {
+            a: alpha.clamp(0.0, 1.0),
+            ..self
+        }
     }
 
-    pub fn to_hex(&self) -> u32 {
-        ((self.r as u32) << 16) | ((self.g as u32) << 8) | (self.b as u32)
+    // Linear components as a vector, for lighting math
+    pub fn to_vec3(self) -> Vec3 {
+        Vec3::new(self.r, self.g, self.b)
+    }
+
+    pub fn from_vec3(v: Vec3) -> Self {
+        Color::linear(v.x.max(0.0), v.y.max(0.0), v.z.max(0.0))
     }
 
     // Linear interpolation between two colors
     pub fn lerp(&self, other: &Color, t: f32) -> Self {
         let t = t.clamp(0.0, 1.0);
         Color {
-            r: (self.r as f32 + (other.r as f32 - self.r as f32) * t).round() as u8,
-            g: (self.g as f32 + (other.g as f32 - self.g as f32) * t).round() as u8,
-            b: (self.b as f32 + (other.b as f32 - self.b as f32) * t).round() as u8,
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
         }
     }
     pub fn blend(color1: Color, color2: Color, factor: f32) -> Color {
-        let r = (color1.r as f32 * (1.0 - factor) + color2.r as f32 * factor) as u8;
-        let g = (color1.g as f32 * (1.0 - factor) + color2.g as f32 * factor) as u8;
-        let b = (color1.b as f32 * (1.0 - factor) + color2.b as f32 * factor) as u8;
-        Color { r, g, b }
+        color1.lerp(&color2, factor)
     }
 
     pub fn apply_intensity(&self, intensity: f32) -> Color {
-        Color {
-            r: (self.r as f32 * intensity).clamp(0.0, 255.0) as u8,
-            g: (self.g as f32 * intensity).clamp(0.0, 255.0) as u8,
-            b: (self.b as f32 * intensity).clamp(0.0, 255.0) as u8,
-        }
+        *self * intensity
     }
 }
 
@@ -60,31 +104,33 @@ impl Add for Color {
 
     fn add(self, other: Color) -> Color {
         Color {
-            r: self.r.saturating_add(other.r),
-            g: self.g.saturating_add(other.g),
-            b: self.b.saturating_add(other.b),
+            r: self.r + other.r,
+            g: self.g + other.g,
+            b: self.b + other.b,
+            a: self.a.max(other.a),
         }
     }
 }
 
 use std::ops::Mul;
 
+// Scales the color, alpha is kept as is
 impl Mul<f32> for Color {
     type Output = Color;
 
     fn mul(self, scalar: f32) -> Color {
+        let scalar = scalar.max(0.0);
         Color {
-            r: (self.r as f32 * scalar).clamp(0.0, 255.0) as u8,
-            g: (self.g as f32 * scalar).clamp(0.0, 255.0) as u8,
-            b: (self.b as f32 * scalar).clamp(0.0, 255.0) as u8,
+            r: self.r * scalar,
+            g: self.g * scalar,
+            b: self.b * scalar,
+            a: self.a,
         }
     }
 }
 
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Color(r: {}, g: {}, b: {})", self.r, self.g, self.b)
+        write!(f, "Color(r: {:.3}, g: {:.3}, b: {:.3}, a: {:.3})", self.r, self.g, self.b, self.a)
     }
 }
-
-
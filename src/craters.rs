@@ -0,0 +1,252 @@
+use std::f32::consts::PI;
+use nalgebra_glm::Vec3;
+use fastnoise_lite::{CellularDistanceFunction, CellularReturnType};
+use crate::noise::{Cellular, Noise, NoiseConfig};
+use crate::utils::{orthonormal_basis, smoothstep};
+
+// Una escala de cráteres: una celda de Voronoi por cráter como mucho
+#[derive(Debug, Clone, Copy)]
+pub struct CraterLayer {
+    // Celdas por unidad del modelo; el punto se proyecta antes a la esfera unitaria
+    pub frequency: f32,
+    // Profundidad del cuenco más grande, en unidades del modelo
+    pub depth: f32,
+    // Radio máximo en fracción de celda (los puntos se separan ~1 celda)
+    pub radius: f32,
+    // Fracción de celdas que tienen cráter
+    pub coverage: f32,
+}
+
+// Relieve y brillo en un punto: la altura va al bump, el brillo multiplica el color
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CraterSample {
+    pub height: f32,
+    pub brightness: f32,
+}
+
+// Generadores de una escala: distancia al punto más cercano, su valor (para sacar
+// tamaño, edad y presencia) y la diferencia con el segundo, para que el cráter se
+// desvanezca antes del borde de la celda en vez de cortarse en seco
+struct Cells {
+    distance: Noise,
+    value: Noise,
+    border: Noise,
+}
+
+impl Cells {
+    fn new(seed: i32) -> Self {
+        let build = |return_type| NoiseConfig::new(seed)
+            .with_frequency(1.0)
+            .cellular(Cellular { distance: CellularDistanceFunction::Euclidean, return_type, jitter: 1.0 })
+            .build();
+        Cells {
+            distance: build(CellularReturnType::Distance),
+            value: build(CellularReturnType::CellValue),
+            border: build(CellularReturnType::Distance2Sub),
+        }
+    }
+
+    // Los generadores devuelven la distancia menos uno
+    fn distance(&self, p: &Vec3) -> f32 {
+        self.distance.get_noise_3d(p.x, p.y, p.z) + 1.0
+    }
+
+    // 1 lejos del borde de la celda y 0 sobre él
+    fn fade(&self, p: &Vec3) -> f32 {
+        smoothstep(0.0, 0.15, self.border.get_noise_3d(p.x, p.y, p.z) + 1.0)
+    }
+}
+
+// Cráteres de impacto sobre ruido celular: cuenco, borde levantado, manto de eyecta,
+// rayos en los jóvenes y relieve más suave cuanto más viejos
+pub struct Craters {
+    // Altura del borde y ancho de su perfil, en fracción de la profundidad y del radio
+    pub rim_height: f32,
+    pub rim_width: f32,
+    // Caída del manto claro de eyecta fuera del borde, en radios
+    pub ejecta_reach: f32,
+    pub ejecta_brightness: f32,
+    // Rayos: cuántos por vuelta, hasta dónde llegan (en radios) y su brillo
+    pub ray_count: f32,
+    pub ray_length: f32,
+    pub ray_brightness: f32,
+    // Edad (en [0, 1]) por debajo de la cual un cráter todavía conserva los rayos
+    pub ray_age: f32,
+    // Cuánto se aplanan el cuenco y el borde en los cráteres más viejos
+    pub erosion: f32,
+    layers: Vec<(CraterLayer, Cells)>,
+    rays: Noise,
+}
+
+impl Craters {
+    // Cada escala usa su propia semilla para que los cráteres no se alineen
+    pub fn new(seed: i32, layers: &[CraterLayer]) -> Self {
+        let layers = layers.iter()
+            .enumerate()
+            .map(|(i, layer)| (*layer, Cells::new(seed.wrapping_add(i as i32 * 101))))
+            .collect();
+        Craters {
+            rim_height: 0.35,
+            rim_width: 0.2,
+            ejecta_reach: 0.6,
+            ejecta_brightness: 0.25,
+            ray_count: 14.0,
+            ray_length: 4.0,
+            ray_brightness: 1.2,
+            ray_age: 0.35,
+            erosion: 0.7,
+            layers,
+            rays: NoiseConfig::new(seed.wrapping_sub(1)).with_frequency(1.0).build(),
+        }
+    }
+
+    // Perfil radial en fracción de la profundidad; r = 1 es la cresta del borde
+    fn profile(&self, r: f32, age: f32) -> f32 {
+        let softening = 1.0 - self.erosion * age;
+        let bowl = if r < 1.0 { r * r - 1.0 } else { 0.0 };
+        let width = self.rim_width * (1.0 + 2.0 * age);
+        let rim = self.rim_height * (-((r - 1.0) / width).powi(2)).exp();
+        (bowl + rim) * softening
+    }
+
+    // Solo el relieve, para el bump, que lo evalúa varias veces por fragmento
+    pub fn height(&self, position: &Vec3) -> f32 {
+        self.layers.iter()
+            .filter_map(|(layer, cells)| {
+                let p = position.normalize() * layer.frequency;
+                let crater = Crater::find(layer, cells, &p)?;
+                let fade = cells.fade(&p);
+                Some(self.profile(cells.distance(&p) / crater.radius, crater.age) * layer.depth * fade)
+            })
+            .sum()
+    }
+
+    pub fn sample(&self, position: &Vec3) -> CraterSample {
+        let mut sample = CraterSample::default();
+        for (layer, cells) in &self.layers {
+            let p = position.normalize() * layer.frequency;
+            let Some(crater) = Crater::find(layer, cells, &p) else { continue };
+            let distance = cells.distance(&p);
+            let r = distance / crater.radius;
+            let fade = cells.fade(&p);
+            sample.height += self.profile(r, crater.age) * layer.depth * fade;
+
+            // Material fresco: el cuenco y el manto de eyecta de los jóvenes son más claros
+            let fresh = (1.0 - crater.age).powi(2);
+            let blanket = if r < 1.0 { 1.0 } else { (-(r - 1.0) / self.ejecta_reach).exp() };
+            let mut brightness = self.ejecta_brightness * fresh * blanket;
+            if crater.age < self.ray_age && r < self.ray_length {
+                brightness += self.ray(cells, &p, distance, r, &crater);
+            }
+            sample.brightness += brightness * fade;
+        }
+        sample
+    }
+
+    // Rayos que salen del centro: el ángulo se mide en una base fija del cráter, cuyo
+    // centro se recupera con el gradiente de la distancia
+    fn ray(&self, cells: &Cells, p: &Vec3, distance: f32, r: f32, crater: &Crater) -> f32 {
+        let epsilon = 1e-3;
+        let gradient = Vec3::new(
+            cells.distance(&(p + Vec3::x() * epsilon)) - distance,
+            cells.distance(&(p + Vec3::y() * epsilon)) - distance,
+            cells.distance(&(p + Vec3::z() * epsilon)) - distance,
+        ) / epsilon;
+        let center = p - gradient * distance;
+        let (tangent, bitangent) = orthonormal_basis(&center);
+        let offset = p - center;
+        let angle = offset.dot(&bitangent).atan2(offset.dot(&tangent));
+
+        // Ruido sobre un círculo para que el patrón se cierre en la vuelta
+        let k = self.ray_count / PI;
+        let seed = crater.hash * 1000.0;
+        let streak = self.rays.get_noise_3d(angle.cos() * k, angle.sin() * k, seed);
+        let streak = smoothstep(0.0, 0.5, streak);
+
+        let reach = smoothstep(0.9, 1.3, r) * (1.0 - smoothstep(1.0, self.ray_length, r));
+        let youth = 1.0 - crater.age / self.ray_age;
+        self.ray_brightness * streak * reach * youth
+    }
+}
+
+impl Default for Craters {
+    // Pocos cráteres grandes y muchos chicos, cada escala menos profunda
+    fn default() -> Self {
+        Craters::new(2024, &[
+            CraterLayer { frequency: 3.0, depth: 0.025, radius: 0.4, coverage: 0.5 },
+            CraterLayer { frequency: 8.0, depth: 0.01, radius: 0.35, coverage: 0.6 },
+            CraterLayer { frequency: 24.0, depth: 0.003, radius: 0.3, coverage: 0.7 },
+        ])
+    }
+}
+
+// Lo que una celda sabe de su cráter
+struct Crater {
+    radius: f32,
+    age: f32,
+    hash: f32,
+}
+
+impl Crater {
+    // El valor de la celda alcanza para sacar varios números independientes
+    fn find(layer: &CraterLayer, cells: &Cells, p: &Vec3) -> Option<Crater> {
+        let hash = cells.value.get_noise_3d(p.x, p.y, p.z) * 0.5 + 0.5;
+        if fract(hash * 113.1) >= layer.coverage {
+            return None;
+        }
+        Some(Crater {
+            radius: layer.radius * (0.4 + 0.6 * fract(hash * 7.13)),
+            // Más cráteres viejos que jóvenes
+            age: fract(hash * 31.7).sqrt(),
+            hash,
+        })
+    }
+}
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::sphere_samples;
+
+    #[test]
+    fn bowls_sink_below_their_raised_rims() {
+        let craters = Craters::default();
+        let young = |r| craters.profile(r, 0.0);
+        assert!(young(0.0) < -0.9);
+        assert!(young(1.0) > 0.3);
+        assert!(young(1.0) > young(1.5) && young(1.5) > young(3.0));
+        assert!(young(3.0).abs() < 1e-3);
+
+        // Con la edad el cuenco se rellena y el borde baja
+        let old = |r| craters.profile(r, 1.0);
+        assert!(old(0.0) > young(0.0) * 0.5);
+        assert!(old(1.0) < young(1.0) * 0.5);
+    }
+
+    #[test]
+    fn surface_stays_within_the_crater_relief() {
+        let layer = CraterLayer { frequency: 8.0, depth: 0.01, radius: 0.3, coverage: 1.0 };
+        let craters = Craters::new(7, &[layer]);
+        let (mut sunken, mut raised, mut bright) = (0, 0, 0);
+        for point in sphere_samples(500) {
+            let sample = craters.sample(&point);
+            assert!((sample.height - craters.height(&point)).abs() < 1e-6);
+            assert!(sample.height >= -layer.depth - 1e-6);
+            assert!(sample.height <= layer.depth * craters.rim_height + 1e-6);
+            sunken += (sample.height < -layer.depth * 0.05) as usize;
+            raised += (sample.height > layer.depth * 0.05) as usize;
+            bright += (sample.brightness > 0.01) as usize;
+        }
+        assert!(sunken > 0 && raised > 0 && bright > 0, "{} {} {}", sunken, raised, bright);
+
+        // Sin cobertura no queda nada
+        let empty = Craters::new(7, &[CraterLayer { coverage: 0.0, ..layer }]);
+        let sample = empty.sample(&Vec3::new(0.3, 0.8, -0.5));
+        assert_eq!((sample.height, sample.brightness), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,173 @@
+use nalgebra_glm::Vec3;
+use crate::noise::Noise;
+use crate::utils::orthonormal_basis;
+use crate::vertex::Vertex;
+use crate::Uniforms;
+
+// Programa de vértices por objeto: recibe y devuelve el vértice en espacio de
+// objeto y corre antes de `vertex_shader`
+pub trait VertexProgram: Send + Sync {
+    fn process(&self, vertex: &Vertex, uniforms: &Uniforms) -> Vertex;
+
+    // Si no depende del tiempo se aplica una sola vez al construir el objeto
+    // (y entonces también lo ven las sombras y el descarte por frustum)
+    fn is_static(&self) -> bool {
+        true
+    }
+}
+
+// Altura sobre la superficie, en unidades del modelo, para un punto sin desplazar
+pub trait HeightField: Send + Sync {
+    fn height(&self, point: &Vec3, noise: &Noise) -> f32;
+}
+
+// Cordilleras: crestas afiladas del ruido del cuerpo
+#[derive(Debug, Clone, Copy)]
+pub struct Mountains {
+    pub scale: f32,
+    pub amplitude: f32,
+    // Exponente de la cresta: más alto, picos más finos
+    pub sharpness: f32,
+    // Por debajo de esta fracción de la cresta el terreno queda plano
+    pub base: f32,
+}
+
+impl Default for Mountains {
+    fn default() -> Self {
+        Mountains { scale: 250.0, amplitude: 0.045, sharpness: 3.0, base: 0.25 }
+    }
+}
+
+impl HeightField for Mountains {
+    fn height(&self, point: &Vec3, noise: &Noise) -> f32 {
+        let p = point * self.scale;
+        let ridge = (1.0 - noise.get_noise_3d(p.x, p.y, p.z).abs()).powf(self.sharpness);
+        ((ridge - self.base) / (1.0 - self.base)).max(0.0) * self.amplitude
+    }
+}
+
+// Conos volcánicos donde el ruido tiene picos, con un cráter hundido en la cima
+#[derive(Debug, Clone, Copy)]
+pub struct Volcanoes {
+    pub scale: f32,
+    pub height: f32,
+    // Valor del ruido desde el que empieza la falda del cono
+    pub threshold: f32,
+    // Fracción superior del cono que ocupa el cráter
+    pub crater: f32,
+    pub crater_depth: f32,
+}
+
+impl Default for Volcanoes {
+    fn default() -> Self {
+        Volcanoes { scale: 120.0, height: 0.07, threshold: 0.3, crater: 0.2, crater_depth: 0.6 }
+    }
+}
+
+impl HeightField for Volcanoes {
+    fn height(&self, point: &Vec3, noise: &Noise) -> f32 {
+        let p = point * self.scale;
+        let peak = ((noise.get_noise_3d(p.x, p.y, p.z) - self.threshold) / (1.0 - self.threshold)).clamp(0.0, 1.0);
+        // Falda cóncava, como la de un estratovolcán
+        let cone = peak * peak;
+        let rim = (1.0 - self.crater).powi(2);
+        if cone > rim {
+            (rim - (cone - rim) * self.crater_depth / (1.0 - rim)) * self.height
+        } else {
+            cone * self.height
+        }
+    }
+}
+
+// Desplaza cada vértice a lo largo de su normal y recalcula la normal con el
+// gradiente del campo de alturas
+pub struct Displacement<H: HeightField> {
+    pub field: H,
+}
+
+impl<H: HeightField> Displacement<H> {
+    pub fn new(field: H) -> Self {
+        Displacement { field }
+    }
+}
+
+impl<H: HeightField> VertexProgram for Displacement<H> {
+    fn process(&self, vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
+        let normal = vertex.normal.normalize();
+        let height = self.field.height(&vertex.position, uniforms.noise);
+
+        // Diferencias finitas sobre el plano tangente
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        let epsilon = vertex.position.magnitude().max(1e-3) * 2e-3;
+        let slope = |direction: &Vec3| (self.field.height(&(vertex.position + direction * epsilon), uniforms.noise) - height) / epsilon;
+
+        let mut displaced = vertex.clone();
+        displaced.position = vertex.position + normal * height;
+        displaced.normal = (normal - tangent * slope(&tangent) - bitangent * slope(&bitangent)).normalize();
+        // La base tangente del vértice se inclina junto con la normal
+        displaced.with_tangents(vertex.tangent, vertex.bitangent)
+    }
+}
+
+// Parte cada triángulo en cuatro, `levels` veces. Pensado para esferas: los puntos
+// nuevos se llevan a la distancia media del centro de sus extremos
+pub fn subdivide(vertices: &[Vertex], levels: u32) -> Vec<Vertex> {
+    let mut vertices = vertices.to_vec();
+    for _ in 0..levels {
+        let mut next = Vec::with_capacity(vertices.len() * 4);
+        for tri in vertices.chunks_exact(3) {
+            let (a, b, c) = (&tri[0], &tri[1], &tri[2]);
+            let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+            next.extend([a.clone(), ab.clone(), ca.clone()]);
+            next.extend([ab.clone(), b.clone(), bc.clone()]);
+            next.extend([ca.clone(), bc.clone(), c.clone()]);
+            next.extend([ab, bc, ca]);
+        }
+        vertices = next;
+    }
+    vertices
+}
+
+fn midpoint(a: &Vertex, b: &Vertex) -> Vertex {
+    let middle = (a.position + b.position) * 0.5;
+    let radius = (a.position.magnitude() + b.position.magnitude()) * 0.5;
+    let position = if middle.magnitude() > 0.0 { middle.normalize() * radius } else { middle };
+    Vertex::new(position, (a.normal + b.normal).normalize(), (a.tex_coords + b.tex_coords) * 0.5)
+        .with_tangents(a.tangent + b.tangent, a.bitangent + b.bitangent)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+
+    // Rampa en x: la normal tiene que inclinarse hacia -x
+    struct Ramp;
+
+    impl HeightField for Ramp {
+        fn height(&self, point: &Vec3, _noise: &Noise) -> f32 {
+            point.x * 0.5
+        }
+    }
+
+    #[test]
+    fn displacement_moves_along_the_normal_and_tilts_it() {
+        let noise = Noise::default();
+        let uniforms = Uniforms::object_space(&noise);
+        let vertex = Vertex::new(Vec3::new(0.2, 0.0, 0.5), Vec3::z(), Vec2::zeros());
+        let displaced = Displacement::new(Ramp).process(&vertex, &uniforms);
+
+        assert!((displaced.position - Vec3::new(0.2, 0.0, 0.6)).magnitude() < 1e-5);
+        let expected = Vec3::new(-0.5, 0.0, 1.0).normalize();
+        assert!((displaced.normal - expected).magnitude() < 1e-3, "{:?}", displaced.normal);
+    }
+
+    #[test]
+    fn subdivision_keeps_points_on_the_sphere() {
+        let tri = [Vec3::x(), Vec3::y(), Vec3::z()].map(|p| Vertex::new(p, p, Vec2::zeros()));
+        let vertices = subdivide(&tri, 2);
+        assert_eq!(vertices.len(), 3 * 16);
+        assert!(vertices.iter().all(|vertex| (vertex.position.magnitude() - 1.0).abs() < 1e-5));
+    }
+}
@@ -1,25 +1,105 @@
+use nalgebra_glm::{Vec2, Vec3, Mat3};
+use crate::utils::{orthonormal_basis, orthonormalize_frame};
 
-use nalgebra_glm::{Vec2, Vec3};
-use crate::color::Color;
-
+#[derive(Clone)]
 pub struct Fragment {
     pub position: Vec2,
-    pub color: Color,
     pub depth: f32,
     pub normal: Vec3,
     pub intensity: f32,
+    // Posición en espacio de objeto: fija sobre la superficie, sin importar la cámara
     pub vertex_position: Vec3,
+    pub world_position: Vec3,
+    pub light_dir: Vec3,
+    pub view_dir: Vec3,
+    // Fracción de la luz principal que dejan pasar otros cuerpos (1 = sin sombra)
+    pub shadow: f32,
+    // Fracción que dejan pasar las nubes del propio planeta; oscurece la luz pero no
+    // vuelve noche el lado de día
+    pub cloud_shadow: f32,
+    pub tex_coords: Vec2,
+    // Base tangente en espacio de mundo; columnas: tangente, bitangente y normal
+    pub tbn: Mat3,
+    // La misma tangente y bitangente en espacio de objeto, para derivar alturas
+    pub vertex_tangent: Vec3,
+    pub vertex_bitangent: Vec3,
 }
 
 impl Fragment {
-    pub fn new(x: f32, y: f32, color: Color, depth: f32, normal: Vec3, intensity: f32, vertex_position: Vec3,) -> Self {
+    pub fn new(x: f32, y: f32, depth: f32, normal: Vec3, intensity: f32, vertex_position: Vec3, world_position: Vec3) -> Self {
+        let (tangent, bitangent) = orthonormal_basis(&normal);
         Fragment {
             position: Vec2::new(x, y),
-            color,
             depth,
             normal,
             intensity,
-            vertex_position
+            vertex_position,
+            world_position,
+            light_dir: normal,
+            view_dir: normal,
+            shadow: 1.0,
+            cloud_shadow: 1.0,
+            tex_coords: Vec2::zeros(),
+            tbn: Mat3::from_columns(&[tangent, bitangent, normal]),
+            vertex_tangent: tangent,
+            vertex_bitangent: bitangent,
         }
     }
-}
\ No newline at end of file
+
+    // Base interpolada de los vértices, ortonormalizada contra la normal del fragmento en
+    // cada espacio. Con coordenadas de textura arbitrarias la base puede girar dentro del
+    // plano tangente, pero las pendientes del bump no dependen de ese giro
+    pub fn set_tangent_frame(&mut self, tangent: Vec3, bitangent: Vec3, vertex_normal: Vec3, vertex_tangent: Vec3, vertex_bitangent: Vec3) {
+        let (tangent, bitangent) = orthonormalize_frame(&self.normal, &tangent, &bitangent);
+        self.tbn = Mat3::from_columns(&[tangent, bitangent, self.normal]);
+        (self.vertex_tangent, self.vertex_bitangent) = orthonormalize_frame(&vertex_normal, &vertex_tangent, &vertex_bitangent);
+    }
+
+    // Fracción de la luz principal que llega a la superficie
+    pub fn sunlight(&self) -> f32 {
+        self.shadow * self.cloud_shadow
+    }
+
+    // Normal en espacio de mundo para una normal dada en espacio tangente (z = sin cambio)
+    pub fn perturb_normal(&self, tangent_normal: &Vec3) -> Vec3 {
+        let normal = self.tbn * tangent_normal;
+        if normal.magnitude_squared() > f32::EPSILON { normal.normalize() } else { self.normal }
+    }
+
+    // Bump mapping: inclina la normal con la pendiente de un campo de alturas en
+    // espacio de objeto, medida a lo largo de la tangente y la bitangente
+    pub fn bump_normal(&self, strength: f32, height: impl Fn(&Vec3) -> f32) -> Vec3 {
+        let position = self.vertex_position;
+        let epsilon = position.magnitude().max(1e-3) * 1e-3;
+        let center = height(&position);
+        let slope = |direction: &Vec3| (height(&(position + direction * epsilon)) - center) / epsilon;
+
+        let tangent_normal = Vec3::new(
+            -slope(&self.vertex_tangent) * strength,
+            -slope(&self.vertex_bitangent) * strength,
+            1.0,
+        );
+        self.perturb_normal(&tangent_normal)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bumps_tilt_the_normal_against_the_slope() {
+        let normal = Vec3::z();
+        let mut fragment = Fragment::new(0.0, 0.0, 0.0, normal, 1.0, Vec3::new(0.0, 0.0, 1.0), Vec3::zeros());
+        fragment.set_tangent_frame(Vec3::x(), Vec3::y(), normal, Vec3::x(), Vec3::y());
+
+        // Plano: la normal no cambia
+        assert!((fragment.bump_normal(1.0, |_| 0.25) - normal).magnitude() < 1e-5);
+
+        // Rampa que sube hacia +x
+        let bumped = fragment.bump_normal(1.0, |point| point.x * 0.5);
+        let expected = Vec3::new(-0.5, 0.0, 1.0).normalize();
+        assert!((bumped - expected).magnitude() < 1e-3, "{:?}", bumped);
+    }
+}
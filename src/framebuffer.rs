@@ -1,11 +1,215 @@
+use nalgebra_glm::Vec3;
+use crate::color::Color;
+use crate::tonemap::ToneMapping;
 
+// Rectángulo en coordenadas de pixel, usado para viewport y scissor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+
+        Rect::new(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+    }
+}
+
+// Cómo se combina el color nuevo con el que ya está en el buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Replace,
+    Alpha,
+    Additive,
+    Multiply,
+}
+
+impl BlendMode {
+    // Mezcla el color destino con la fuente usando la opacidad de la fuente (en lineal)
+    pub fn blend(self, dst: Vec3, src: Vec3, alpha: f32) -> Vec3 {
+        match self {
+            BlendMode::Replace => src,
+            BlendMode::Alpha => src * alpha + dst * (1.0 - alpha),
+            BlendMode::Additive => dst + src * alpha,
+            BlendMode::Multiply => dst.component_mul(&(Vec3::repeat(1.0 - alpha) + src * alpha)),
+        }
+    }
+
+    // La misma mezcla escrita como destino * multiply + add
+    fn affine(self, src: Vec3, alpha: f32) -> (Vec3, Vec3) {
+        match self {
+            BlendMode::Replace => (Vec3::zeros(), src),
+            BlendMode::Alpha => (Vec3::repeat(1.0 - alpha), src * alpha),
+            BlendMode::Additive => (Vec3::repeat(1.0), src * alpha),
+            BlendMode::Multiply => (Vec3::repeat(1.0 - alpha) + src * alpha, Vec3::zeros()),
+        }
+    }
+}
+
+// Fragmento transparente pendiente de resolverse en orden de profundidad
+#[derive(Debug, Clone, Copy)]
+struct TransparentFragment {
+    depth: f32,
+    color: Vec3,
+    alpha: f32,
+    blend_mode: BlendMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LayerNode {
+    fragment: TransparentFragment,
+    next: u32,
+}
+
+const NO_NODE: u32 = u32::MAX;
+
+// Fragmentos que no cupieron en la lista, fundidos en uno solo. Toda mezcla es
+// afín por canal (destino * multiply + add), así que la cola guarda la
+// composición de las suyas y se resuelve detrás de las capas conservadas.
+#[derive(Debug, Clone, Copy)]
+struct TailFragment {
+    // Profundidad del miembro más cercano
+    depth: f32,
+    multiply: Vec3,
+    add: Vec3,
+}
+
+impl TailFragment {
+    fn new(fragment: &TransparentFragment) -> Self {
+        let (multiply, add) = fragment.blend_mode.affine(fragment.color, fragment.alpha);
+        TailFragment { depth: fragment.depth, multiply, add }
+    }
+
+    // Los fragmentos más cercanos que la cola se mezclan encima de ella y los
+    // demás debajo; es exacto si los sobrantes llegan ordenados
+    fn merge(&mut self, fragment: &TransparentFragment) {
+        let (multiply, add) = fragment.blend_mode.affine(fragment.color, fragment.alpha);
+        if fragment.depth < self.depth {
+            self.add = self.add.component_mul(&multiply) + add;
+            self.depth = fragment.depth;
+        } else {
+            self.add += add.component_mul(&self.multiply);
+        }
+        self.multiply = self.multiply.component_mul(&multiply);
+    }
+
+    fn blend(&self, dst: Vec3) -> Vec3 {
+        dst.component_mul(&self.multiply) + self.add
+    }
+}
+
+// A-buffer: lista por pixel de los fragmentos transparentes más cercanos, con un
+// máximo de capas; los que no caben van a una cola por pixel
+struct ABuffer {
+    max_layers: usize,
+    heads: Vec<u32>,
+    counts: Vec<u8>,
+    nodes: Vec<LayerNode>,
+    tails: Vec<u32>,
+    tail_nodes: Vec<TailFragment>,
+    // Pixeles con fragmentos, para resolver y limpiar sin recorrer toda la pantalla
+    touched: Vec<usize>,
+    scratch: Vec<TransparentFragment>,
+}
+
+impl ABuffer {
+    fn new(pixels: usize, max_layers: usize) -> Self {
+        ABuffer {
+            max_layers: max_layers.clamp(1, u8::MAX as usize),
+            heads: vec![NO_NODE; pixels],
+            counts: vec![0; pixels],
+            nodes: Vec::new(),
+            tails: vec![NO_NODE; pixels],
+            tail_nodes: Vec::new(),
+            touched: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        for &index in &self.touched {
+            self.heads[index] = NO_NODE;
+            self.counts[index] = 0;
+            self.tails[index] = NO_NODE;
+        }
+        self.touched.clear();
+        self.nodes.clear();
+        self.tail_nodes.clear();
+    }
+
+    // Guarda el fragmento; si la lista está llena conserva los más cercanos y
+    // funde el más lejano en la cola del pixel
+    fn insert(&mut self, index: usize, fragment: TransparentFragment) {
+        if (self.counts[index] as usize) < self.max_layers {
+            if self.counts[index] == 0 {
+                self.touched.push(index);
+            }
+            self.nodes.push(LayerNode { fragment, next: self.heads[index] });
+            self.heads[index] = (self.nodes.len() - 1) as u32;
+            self.counts[index] += 1;
+            return;
+        }
+
+        let mut farthest = self.heads[index];
+        let mut node = self.heads[index];
+        while node != NO_NODE {
+            if self.nodes[node as usize].fragment.depth > self.nodes[farthest as usize].fragment.depth {
+                farthest = node;
+            }
+            node = self.nodes[node as usize].next;
+        }
+
+        let stored = &mut self.nodes[farthest as usize].fragment;
+        let overflow = if fragment.depth < stored.depth {
+            std::mem::replace(stored, fragment)
+        } else {
+            fragment
+        };
+
+        match self.tails[index] {
+            NO_NODE => {
+                self.tail_nodes.push(TailFragment::new(&overflow));
+                self.tails[index] = (self.tail_nodes.len() - 1) as u32;
+            }
+            tail => self.tail_nodes[tail as usize].merge(&overflow),
+        }
+    }
+}
+
+// Framebuffer HDR: el color se acumula en lineal con f32 y se convierte a
+// 0xRRGGBB en `buffer` al aplicar el mapeo de tonos
 pub struct Framebuffer {
     pub width: usize,
     pub height: usize,
+    pub color_buffer: Vec<Vec3>,
     pub buffer: Vec<u32>,
     pub zbuffer: Vec<f32>,
-    background_color: u32,
-    current_color: u32,
+    background_color: Vec3,
+    current_color: Vec3,
+    current_alpha: f32,
+    blend_mode: BlendMode,
+    depth_write: bool,
+    scissor: Option<Rect>,
+    a_buffer: Option<ABuffer>,
 }
 
 impl Framebuffer {
@@ -13,38 +217,282 @@ impl Framebuffer {
         Framebuffer {
             width,
             height,
+            color_buffer: vec![Vec3::zeros(); width * height],
             buffer: vec![0; width * height],
             zbuffer: vec![f32::INFINITY; width * height],
-            background_color: 0x000000,
-            current_color: 0xFFFFFF
+            background_color: Vec3::zeros(),
+            current_color: Vec3::new(1.0, 1.0, 1.0),
+            current_alpha: 1.0,
+            blend_mode: BlendMode::Replace,
+            depth_write: true,
+            scissor: None,
+            a_buffer: None,
+        }
+    }
+
+    // Framebuffer sin color, solo profundidad (mapas de sombra)
+    pub fn depth_only(width: usize, height: usize) -> Self {
+        Framebuffer {
+            color_buffer: Vec::new(),
+            buffer: Vec::new(),
+            ..Framebuffer::new(width, height)
         }
     }
 
     pub fn clear(&mut self) {
-        for pixel in self.buffer.iter_mut() {
+        for pixel in self.color_buffer.iter_mut() {
             *pixel = self.background_color;
         }
         for depth in self.zbuffer.iter_mut() {
             *depth = f32::INFINITY;
         }
+        if let Some(a_buffer) = &mut self.a_buffer {
+            a_buffer.clear();
+        }
     }
 
     pub fn point(&mut self, x: usize, y: usize, depth: f32) {
-        if x < self.width && y < self.height {
+        if self.clip_rect().contains(x, y) {
             let index = y * self.width + x;
 
             if self.zbuffer[index] > depth {
-                self.buffer[index] = self.current_color;
+                // Con A-buffer los fragmentos mezclados se guardan y se resuelven al final
+                if self.blend_mode != BlendMode::Replace && !self.color_buffer.is_empty() {
+                    if let Some(a_buffer) = &mut self.a_buffer {
+                        let fragment = TransparentFragment {
+                            depth,
+                            color: self.current_color,
+                            alpha: self.current_alpha,
+                            blend_mode: self.blend_mode,
+                        };
+                        a_buffer.insert(index, fragment);
+                        return;
+                    }
+                }
+
+                if let Some(pixel) = self.color_buffer.get_mut(index) {
+                    *pixel = self.blend_mode.blend(*pixel, self.current_color, self.current_alpha);
+                }
+                if self.depth_write {
+                    self.zbuffer[index] = depth;
+                }
+            }
+        }
+    }
+
+    pub fn depth_point(&mut self, x: usize, y: usize, depth: f32) {
+        if self.clip_rect().contains(x, y) {
+            let index = y * self.width + x;
+            if self.zbuffer[index] > depth {
                 self.zbuffer[index] = depth;
             }
         }
     }
 
     pub fn set_background_color(&mut self, color: u32) {
-        self.background_color = color;
+        self.background_color = Color::from_hex(color).to_vec3();
+    }
+
+    // Color lineal (puede pasar de 1.0) y opacidad para los siguientes puntos
+    pub fn set_current_color(&mut self, color: Color) {
+        self.current_color = color.to_vec3();
+        self.current_alpha = color.alpha();
+    }
+
+    // Aplica exposición, mapeo de tonos y codificación sRGB sobre `buffer`
+    pub fn tone_map(&mut self, tone_mapping: &ToneMapping) {
+        for (pixel, color) in self.buffer.iter_mut().zip(&self.color_buffer) {
+            *pixel = tone_mapping.encode(*color);
+        }
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    // Los objetos transparentes se dibujan sin escribir profundidad
+    pub fn set_depth_write(&mut self, depth_write: bool) {
+        self.depth_write = depth_write;
+    }
+
+    // Transparencia independiente del orden: guarda hasta `max_layers` fragmentos
+    // transparentes por pixel; los que no caben se funden en una cola detrás de ellos
+    pub fn enable_a_buffer(&mut self, max_layers: usize) {
+        self.a_buffer = Some(ABuffer::new(self.width * self.height, max_layers));
+    }
+
+    pub fn disable_a_buffer(&mut self) {
+        self.a_buffer = None;
     }
 
-    pub fn set_current_color(&mut self, color: u32) {
-        self.current_color = color;
+    pub fn a_buffer_enabled(&self) -> bool {
+        self.a_buffer.is_some()
+    }
+
+    // Mezcla los fragmentos guardados de atrás hacia adelante sobre los opacos
+    pub fn resolve_transparency(&mut self) {
+        let a_buffer = match &mut self.a_buffer {
+            Some(a_buffer) => a_buffer,
+            None => return,
+        };
+
+        for &index in &a_buffer.touched {
+            a_buffer.scratch.clear();
+            let mut node = a_buffer.heads[index];
+            while node != NO_NODE {
+                let layer = a_buffer.nodes[node as usize];
+                a_buffer.scratch.push(layer.fragment);
+                node = layer.next;
+            }
+
+            // La cola está detrás de todas las capas guardadas
+            let tail = a_buffer.tails[index];
+            if tail != NO_NODE && a_buffer.tail_nodes[tail as usize].depth < self.zbuffer[index] {
+                self.color_buffer[index] = a_buffer.tail_nodes[tail as usize].blend(self.color_buffer[index]);
+            }
+
+            a_buffer.scratch.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+            for fragment in &a_buffer.scratch {
+                // Un opaco dibujado después del fragmento pudo haberlo tapado
+                if fragment.depth < self.zbuffer[index] {
+                    self.color_buffer[index] = fragment.blend_mode.blend(self.color_buffer[index], fragment.color, fragment.alpha);
+                }
+            }
+        }
+
+        a_buffer.clear();
+    }
+
+    // Limita el dibujo a una región (pantalla dividida, paneles de UI)
+    pub fn set_scissor(&mut self, rect: Rect) {
+        self.scissor = Some(rect);
+    }
+
+    pub fn clear_scissor(&mut self) {
+        self.scissor = None;
+    }
+
+    pub fn viewport(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    // Región donde se puede rasterizar: viewport intersectado con el scissor
+    pub fn clip_rect(&self) -> Rect {
+        match &self.scissor {
+            Some(scissor) => self.viewport().intersect(scissor),
+            None => self.viewport(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rects_partly_off_screen_are_cut_to_the_overlap() {
+        let screen = Rect::new(0, 0, 800, 600);
+
+        assert_eq!(screen.intersect(&Rect::new(700, 500, 300, 300)), Rect::new(700, 500, 100, 100));
+        assert_eq!(Rect::new(0, 550, 2000, 100).intersect(&screen), Rect::new(0, 550, 800, 50));
+        assert_eq!(screen.intersect(&Rect::new(0, 0, 10_000, 10_000)), screen);
+    }
+
+    #[test]
+    fn rects_fully_off_screen_intersect_to_nothing() {
+        let screen = Rect::new(0, 0, 800, 600);
+
+        assert!(screen.intersect(&Rect::new(800, 0, 100, 100)).is_empty());
+        assert!(screen.intersect(&Rect::new(900, 700, 50, 50)).is_empty());
+        assert!(Rect::new(0, 600, 800, 10).intersect(&screen).is_empty());
+    }
+
+    #[test]
+    fn the_scissor_limits_the_clip_rect_and_point_writes() {
+        let mut framebuffer = Framebuffer::new(8, 8);
+        framebuffer.set_scissor(Rect::new(6, 6, 10, 10));
+        assert_eq!(framebuffer.clip_rect(), Rect::new(6, 6, 2, 2));
+
+        framebuffer.point(2, 2, 0.0);
+        framebuffer.point(7, 7, 0.0);
+        assert_eq!(framebuffer.zbuffer[2 * 8 + 2], f32::INFINITY);
+        assert_eq!(framebuffer.zbuffer[7 * 8 + 7], 0.0);
+
+        framebuffer.clear_scissor();
+        assert_eq!(framebuffer.clip_rect(), framebuffer.viewport());
+    }
+
+    // Framebuffer de un pixel con A-buffer y mezcla alfa
+    fn transparent_pixel(max_layers: usize) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.enable_a_buffer(max_layers);
+        framebuffer.set_blend_mode(BlendMode::Alpha);
+        framebuffer.set_depth_write(false);
+        framebuffer
+    }
+
+    fn layers() -> Vec<(f32, Color)> {
+        vec![
+            (0.6, Color::linear(1.0, 0.0, 0.0).with_alpha(0.5)),
+            (0.3, Color::linear(0.0, 1.0, 0.0).with_alpha(0.4)),
+            (0.9, Color::linear(0.0, 0.0, 1.0).with_alpha(0.7)),
+            (0.5, Color::linear(1.0, 1.0, 0.0).with_alpha(0.3)),
+            (0.8, Color::linear(0.0, 1.0, 1.0).with_alpha(0.6)),
+        ]
+    }
+
+    // Mezcla de atrás hacia adelante, la referencia de lo que debe resolver el A-buffer
+    fn sorted_blend(layers: &[(f32, Color)]) -> Vec3 {
+        let mut sorted = layers.to_vec();
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        sorted.iter().fold(Vec3::zeros(), |dst, (_, color)| BlendMode::Alpha.blend(dst, color.to_vec3(), color.alpha()))
+    }
+
+    fn draw(framebuffer: &mut Framebuffer, layers: &[(f32, Color)]) -> Vec3 {
+        for (depth, color) in layers {
+            framebuffer.set_current_color(*color);
+            framebuffer.point(0, 0, *depth);
+        }
+        framebuffer.resolve_transparency();
+        framebuffer.color_buffer[0]
+    }
+
+    #[test]
+    fn layers_resolve_back_to_front_whatever_the_draw_order() {
+        let expected = sorted_blend(&layers());
+        let resolved = draw(&mut transparent_pixel(8), &layers());
+        assert!((resolved - expected).magnitude() < 1e-5, "{:?} != {:?}", resolved, expected);
+    }
+
+    #[test]
+    fn overflow_keeps_the_nearest_layers_and_merges_the_rest_behind_them() {
+        // Dos capas caben; los sobrantes llegan de cerca a lejos o de lejos a cerca
+        for order in [[2, 4, 0, 3, 1], [1, 3, 0, 4, 2]] {
+            let drawn = order.iter().map(|&i| layers()[i]).collect::<Vec<_>>();
+            let expected = sorted_blend(&drawn);
+            let resolved = draw(&mut transparent_pixel(2), &drawn);
+            assert!((resolved - expected).magnitude() < 1e-5, "{:?}: {:?} != {:?}", order, resolved, expected);
+        }
+    }
+
+    #[test]
+    fn opaque_surfaces_in_front_hide_the_overflow() {
+        let mut framebuffer = transparent_pixel(1);
+        for (depth, color) in layers() {
+            framebuffer.set_current_color(color);
+            framebuffer.point(0, 0, depth);
+        }
+
+        framebuffer.set_blend_mode(BlendMode::Replace);
+        framebuffer.set_depth_write(true);
+        framebuffer.set_current_color(Color::linear(0.2, 0.2, 0.2));
+        framebuffer.point(0, 0, 0.1);
+        framebuffer.resolve_transparency();
+        assert_eq!(framebuffer.color_buffer[0], Vec3::repeat(0.2));
     }
 }
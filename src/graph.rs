@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use nalgebra_glm::Vec3;
+use crate::color::Color;
+use crate::fragment::Fragment;
+use crate::light::{blinn_phong, Specular};
+use crate::material::{Material, Surface};
+use crate::noise::Fractal;
+use crate::utils::smoothstep;
+use crate::Uniforms;
+
+// Índice de un nodo dentro de su grafo
+pub type NodeId = usize;
+
+// Nodos del grafo. Todos producen un Vec3: los escalares se repiten en los tres
+// componentes y las operaciones se aplican componente a componente
+#[derive(Debug, Clone)]
+pub enum Node {
+    // Posición en espacio de objeto del fragmento
+    Position,
+    // Tiempo de la simulación
+    Time,
+    Constant(Vec3),
+    // Ruido del cuerpo en (entrada * escala)
+    Noise { input: NodeId, scale: f32 },
+    // Suma de octavas normalizada a [-1, 1]
+    Fbm { input: NodeId, fractal: Fractal },
+    // Crestas afiladas (1 - |ruido|)², normalizadas a [0, 1]
+    Ridged { input: NodeId, fractal: Fractal },
+    // Desplaza una posición con el propio ruido antes de muestrear
+    Warp { input: NodeId, amplitude: f32, scale: f32 },
+    // Un componente (0 = x, 1 = y, 2 = z) repetido en los tres
+    Component { input: NodeId, axis: usize },
+    Add(NodeId, NodeId),
+    Sub(NodeId, NodeId),
+    Mul(NodeId, NodeId),
+    Min(NodeId, NodeId),
+    Max(NodeId, NodeId),
+    Abs(NodeId),
+    Sin(NodeId),
+    Cos(NodeId),
+    Clamp { input: NodeId, min: f32, max: f32 },
+    // Lleva [from.0, from.1] a [to.0, to.1], limitado al rango de salida
+    Remap { input: NodeId, from: (f32, f32), to: (f32, f32) },
+    // 1 donde la entrada supera el umbral (o queda debajo si `below`), con borde suave opcional
+    Mask { input: NodeId, threshold: f32, softness: f32, below: bool },
+    // a + (b - a) * t, con t limitado a [0, 1]
+    Mix { a: NodeId, b: NodeId, t: NodeId },
+    // Interpolación entre paradas (posición, color) ordenadas
+    Gradient { input: NodeId, stops: Vec<(f32, NodeId)> },
+    // Bandas de color sin interpolar: la entrada en [0, 1] elige el índice
+    Palette { input: NodeId, colors: Vec<NodeId> },
+}
+
+// Superficie procedural descrita como grafo de nodos y evaluada por fragmento
+#[derive(Debug, Clone)]
+pub struct ShaderGraph {
+    nodes: Vec<Node>,
+    color: Option<NodeId>,
+    specular_mask: Option<NodeId>,
+    emission: Option<NodeId>,
+    specular: Specular,
+}
+
+impl ShaderGraph {
+    pub fn new() -> Self {
+        ShaderGraph {
+            nodes: Vec::new(),
+            color: None,
+            specular_mask: None,
+            emission: None,
+            specular: Specular::matte(),
+        }
+    }
+
+    // Agrega un nodo; sus entradas tienen que existir ya, así el orden de
+    // inserción es un orden de evaluación válido
+    pub fn node(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn constant(&mut self, value: f32) -> NodeId {
+        self.node(Node::Constant(Vec3::repeat(value)))
+    }
+
+    // Color en sRGB de 8 bits, guardado en lineal
+    pub fn rgb(&mut self, r: u8, g: u8, b: u8) -> NodeId {
+        self.node(Node::Constant(Color::new(r, g, b).to_vec3()))
+    }
+
+    pub fn set_color(&mut self, node: NodeId) {
+        self.color = Some(node);
+    }
+
+    pub fn set_specular(&mut self, specular: Specular, mask: Option<NodeId>) {
+        self.specular = specular;
+        self.specular_mask = mask;
+    }
+
+    // Luz propia que se suma después de iluminar
+    pub fn set_emission(&mut self, node: NodeId) {
+        self.emission = Some(node);
+    }
+
+    // Evalúa todos los nodos en orden y devuelve el valor de cada uno
+    pub fn evaluate(&self, position: &Vec3, uniforms: &Uniforms) -> Vec<Vec3> {
+        let mut values: Vec<Vec3> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let value = match node {
+                Node::Position => *position,
+                Node::Time => Vec3::repeat(uniforms.time),
+                Node::Constant(value) => *value,
+                Node::Noise { input, scale } => {
+                    let p = values[*input] * *scale;
+                    Vec3::repeat(uniforms.noise.get_noise_3d(p.x, p.y, p.z))
+                }
+                Node::Fbm { input, fractal } => {
+                    Vec3::repeat(uniforms.noise.fractal(&values[*input], *fractal, |n| n))
+                }
+                Node::Ridged { input, fractal } => {
+                    Vec3::repeat(uniforms.noise.fractal(&values[*input], *fractal, |n| (1.0 - n.abs()).powi(2)))
+                }
+                Node::Warp { input, amplitude, scale } => {
+                    let p = values[*input] * *scale;
+                    // Tres muestras desplazadas para que cada eje se deforme distinto
+                    let offset = Vec3::new(
+                        uniforms.noise.get_noise_3d(p.x, p.y, p.z),
+                        uniforms.noise.get_noise_3d(p.x + 131.7, p.y, p.z - 47.3),
+                        uniforms.noise.get_noise_3d(p.x - 71.1, p.y + 93.5, p.z),
+                    );
+                    values[*input] + offset * *amplitude
+                }
+                Node::Component { input, axis } => Vec3::repeat(values[*input][*axis]),
+                Node::Add(a, b) => values[*a] + values[*b],
+                Node::Sub(a, b) => values[*a] - values[*b],
+                Node::Mul(a, b) => values[*a].component_mul(&values[*b]),
+                Node::Min(a, b) => values[*a].zip_map(&values[*b], f32::min),
+                Node::Max(a, b) => values[*a].zip_map(&values[*b], f32::max),
+                Node::Abs(a) => values[*a].abs(),
+                Node::Sin(a) => values[*a].map(f32::sin),
+                Node::Cos(a) => values[*a].map(f32::cos),
+                Node::Clamp { input, min, max } => values[*input].map(|v| v.clamp(*min, *max)),
+                Node::Remap { input, from, to } => values[*input].map(|v| {
+                    let t = ((v - from.0) / (from.1 - from.0)).clamp(0.0, 1.0);
+                    to.0 + (to.1 - to.0) * t
+                }),
+                Node::Mask { input, threshold, softness, below } => values[*input].map(|v| {
+                    let v = if *below { -v } else { v };
+                    let threshold = if *below { -threshold } else { *threshold };
+                    if *softness > 0.0 {
+                        smoothstep(threshold - softness, threshold + softness, v)
+                    } else if v > threshold {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }),
+                Node::Mix { a, b, t } => {
+                    let t = values[*t].map(|t| t.clamp(0.0, 1.0));
+                    values[*a] + (values[*b] - values[*a]).component_mul(&t)
+                }
+                Node::Gradient { input, stops } => gradient(values[*input].x, stops, &values),
+                Node::Palette { input, colors } => {
+                    let index = (values[*input].x.clamp(0.0, 1.0) * colors.len() as f32) as usize;
+                    values[colors[index.min(colors.len() - 1)]]
+                }
+            };
+            values.push(value);
+        }
+        values
+    }
+
+    // Si alguna salida depende, directa o indirectamente, del nodo de tiempo
+    pub fn depends_on_time(&self) -> bool {
+        let mut timed = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let inputs: Vec<NodeId> = match node {
+                Node::Time => {
+                    timed.push(true);
+                    continue;
+                }
+                Node::Position | Node::Constant(_) => Vec::new(),
+                Node::Noise { input, .. }
+                | Node::Fbm { input, .. }
+                | Node::Ridged { input, .. }
+                | Node::Warp { input, .. }
+                | Node::Component { input, .. }
+                | Node::Clamp { input, .. }
+                | Node::Remap { input, .. }
+                | Node::Mask { input, .. } => vec![*input],
+                Node::Abs(a) | Node::Sin(a) | Node::Cos(a) => vec![*a],
+                Node::Add(a, b) | Node::Sub(a, b) | Node::Mul(a, b) | Node::Min(a, b) | Node::Max(a, b) => vec![*a, *b],
+                Node::Mix { a, b, t } => vec![*a, *b, *t],
+                Node::Gradient { input, stops } => stops.iter().map(|stop| stop.1).chain([*input]).collect(),
+                Node::Palette { input, colors } => colors.iter().copied().chain([*input]).collect(),
+            };
+            timed.push(inputs.iter().any(|&input| timed[input]));
+        }
+        [self.color, self.specular_mask, self.emission].into_iter().flatten().any(|node| timed[node])
+    }
+
+    // Lee un grafo en el formato del archivo de escena (sin las líneas
+    // `material` / `end` que lo delimitan)
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = GraphParser::new();
+        for (number, line) in source.lines().enumerate() {
+            parser.line(line).map_err(|error| format!("línea {}: {}", number + 1, error))?;
+        }
+        parser.finish()
+    }
+}
+
+impl Default for ShaderGraph {
+    fn default() -> Self {
+        ShaderGraph::new()
+    }
+}
+
+impl Material for ShaderGraph {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let values = self.evaluate(&fragment.vertex_position, uniforms);
+        let color = self.color.map_or(Vec3::zeros(), |node| values[node]);
+        let specular_mask = self.specular_mask.map_or(1.0, |node| values[node].x);
+        let emission = self.emission.map_or(Vec3::zeros(), |node| values[node].map(|c| c.max(0.0)));
+
+        Surface::new(Color::from_vec3(color))
+            .with_specular_mask(specular_mask)
+            .with_emission(emission)
+    }
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let lit_color = blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular)
+            .apply(surface.albedo, surface.specular_mask);
+        lit_color + Color::from_vec3(surface.emission)
+    }
+
+    // Un grafo que lee `time` queda congelado si se hornea
+    fn bakeable(&self) -> bool {
+        !self.depends_on_time()
+    }
+}
+
+fn gradient(t: f32, stops: &[(f32, NodeId)], values: &[Vec3]) -> Vec3 {
+    let Some(&(first, first_color)) = stops.first() else {
+        return Vec3::zeros();
+    };
+    if t <= first {
+        return values[first_color];
+    }
+    for pair in stops.windows(2) {
+        let ((start, from), (end, to)) = (pair[0], pair[1]);
+        if t <= end {
+            let f = ((t - start) / (end - start).max(1e-6)).clamp(0.0, 1.0);
+            return values[from] + (values[to] - values[from]) * f;
+        }
+    }
+    values[stops[stops.len() - 1].1]
+}
+
+
+// Lectura del formato de texto: una asignación por línea, `nombre = op args...`,
+// donde cada argumento es un número o el nombre de un nodo anterior (`position` y
+// `time` ya existen). Las líneas `color`, `specular` y `emission` definen la salida
+struct GraphParser {
+    graph: ShaderGraph,
+    names: HashMap<String, NodeId>,
+}
+
+impl GraphParser {
+    fn new() -> Self {
+        let mut graph = ShaderGraph::new();
+        let mut names = HashMap::new();
+        names.insert("position".to_string(), graph.node(Node::Position));
+        names.insert("time".to_string(), graph.node(Node::Time));
+        GraphParser { graph, names }
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if let Some((name, expression)) = line.split_once('=') {
+            let name = name.trim();
+            if name.is_empty() || name.parse::<f32>().is_ok() || name.contains(char::is_whitespace) {
+                return Err(format!("nombre de nodo inválido '{}'", name));
+            }
+            let words = expression.split_whitespace().collect::<Vec<_>>();
+            let node = self.expression(&words)?;
+            self.names.insert(name.to_string(), node);
+            return Ok(());
+        }
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["color", node] => {
+                let node = self.operand(node)?;
+                self.graph.set_color(node);
+            }
+            ["emission", node] => {
+                let node = self.operand(node)?;
+                self.graph.set_emission(node);
+            }
+            // specular brillo fuerza [r g b] [mask nodo]
+            ["specular", rest @ ..] => {
+                let (numbers, mask) = match rest {
+                    [numbers @ .., "mask", node] => (numbers, Some(self.operand(node)?)),
+                    _ => (rest, None),
+                };
+                let numbers = numbers.iter().map(|word| number(word)).collect::<Result<Vec<_>, _>>()?;
+                let color = match numbers.as_slice() {
+                    [_, _] => Vec3::new(1.0, 1.0, 1.0),
+                    [_, _, r, g, b] => Vec3::new(*r, *g, *b),
+                    _ => return Err("specular espera brillo, fuerza y un color opcional".to_string()),
+                };
+                self.graph.set_specular(Specular::new(numbers[0], color, numbers[1]), mask);
+            }
+            _ => return Err(format!("no se entiende '{}'", line)),
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, words: &[&str]) -> Result<NodeId, String> {
+        let Some((&op, args)) = words.split_first() else {
+            return Err("falta la operación".to_string());
+        };
+        let node = match (op, args) {
+            ("rgb", [r, g, b]) => {
+                let channel = |word: &str| word.parse::<u8>().map_err(|_| format!("canal inválido '{}'", word));
+                return Ok(self.graph.rgb(channel(r)?, channel(g)?, channel(b)?));
+            }
+            ("noise", [input, scale]) => Node::Noise { input: self.operand(input)?, scale: number(scale)? },
+            ("fbm" | "ridged", [input, scale, octaves, lacunarity, gain]) => {
+                let (input, scale) = (self.operand(input)?, number(scale)?);
+                let octaves = octaves.parse::<u32>().map_err(|_| format!("octavas inválidas '{}'", octaves))?;
+                let fractal = Fractal::new(scale, octaves, number(lacunarity)?, number(gain)?);
+                if op == "fbm" {
+                    Node::Fbm { input, fractal }
+                } else {
+                    Node::Ridged { input, fractal }
+                }
+            }
+            ("warp", [input, amplitude, scale]) => Node::Warp { input: self.operand(input)?, amplitude: number(amplitude)?, scale: number(scale)? },
+            ("x", [input]) => Node::Component { input: self.operand(input)?, axis: 0 },
+            ("y", [input]) => Node::Component { input: self.operand(input)?, axis: 1 },
+            ("z", [input]) => Node::Component { input: self.operand(input)?, axis: 2 },
+            // add y mul aceptan más de dos operandos y se encadenan
+            ("add" | "mul", [first, rest @ ..]) if !rest.is_empty() => {
+                let mut node = self.operand(first)?;
+                for operand in rest {
+                    let operand = self.operand(operand)?;
+                    node = self.graph.node(if op == "add" { Node::Add(node, operand) } else { Node::Mul(node, operand) });
+                }
+                return Ok(node);
+            }
+            ("sub", [a, b]) => Node::Sub(self.operand(a)?, self.operand(b)?),
+            ("min", [a, b]) => Node::Min(self.operand(a)?, self.operand(b)?),
+            ("max", [a, b]) => Node::Max(self.operand(a)?, self.operand(b)?),
+            ("abs", [a]) => Node::Abs(self.operand(a)?),
+            ("sin", [a]) => Node::Sin(self.operand(a)?),
+            ("cos", [a]) => Node::Cos(self.operand(a)?),
+            ("clamp", [input, min, max]) => Node::Clamp { input: self.operand(input)?, min: number(min)?, max: number(max)? },
+            ("remap", [input, from_min, from_max, to_min, to_max]) => Node::Remap {
+                input: self.operand(input)?,
+                from: (number(from_min)?, number(from_max)?),
+                to: (number(to_min)?, number(to_max)?),
+            },
+            // mask valor > umbral [suavidad]  /  mask valor < umbral [suavidad]
+            ("mask", [input, comparison, threshold, softness @ ..]) => {
+                let below = match *comparison {
+                    ">" => false,
+                    "<" => true,
+                    other => return Err(format!("comparación inválida '{}'", other)),
+                };
+                let softness = match softness {
+                    [] => 0.0,
+                    [softness] => number(softness)?,
+                    _ => return Err("mask espera a lo sumo una suavidad".to_string()),
+                };
+                Node::Mask { input: self.operand(input)?, threshold: number(threshold)?, softness, below }
+            }
+            ("mix", [a, b, t]) => Node::Mix { a: self.operand(a)?, b: self.operand(b)?, t: self.operand(t)? },
+            // gradient valor pos color pos color ...
+            ("gradient", [input, stops @ ..]) if !stops.is_empty() && stops.len() % 2 == 0 => {
+                let input = self.operand(input)?;
+                let stops = stops.chunks(2)
+                    .map(|stop| Ok((number(stop[0])?, self.operand(stop[1])?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Node::Gradient { input, stops }
+            }
+            ("palette", [input, colors @ ..]) if !colors.is_empty() => {
+                let input = self.operand(input)?;
+                let colors = colors.iter().map(|color| self.operand(color)).collect::<Result<Vec<_>, _>>()?;
+                Node::Palette { input, colors }
+            }
+            _ => return Err(format!("operación desconocida o argumentos inválidos: '{}'", words.join(" "))),
+        };
+        Ok(self.graph.node(node))
+    }
+
+    // Un número se vuelve un nodo constante; un nombre tiene que estar definido antes
+    fn operand(&mut self, word: &str) -> Result<NodeId, String> {
+        if let Ok(value) = word.parse::<f32>() {
+            return Ok(self.graph.constant(value));
+        }
+        self.names.get(word).copied().ok_or_else(|| format!("nodo no definido '{}'", word))
+    }
+
+    fn finish(self) -> Result<ShaderGraph, String> {
+        if self.graph.color.is_none() {
+            return Err("falta la línea 'color'".to_string());
+        }
+        Ok(self.graph)
+    }
+}
+
+fn number(word: &str) -> Result<f32, String> {
+    word.parse::<f32>().map_err(|_| format!("número inválido '{}'", word))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Mat4;
+    use crate::light::Light;
+    use crate::noise::{Noise, NoiseConfig};
+    use crate::shaders::{IcyMaterial, RockyMaterial};
+    use crate::material::MaterialRegistry;
+    use fastnoise_lite::FractalType;
+
+    fn uniforms_from<'a>(lights: &'a [Light], noise: &'a Noise) -> Uniforms<'a> {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 42.0,
+            noise,
+            lights,
+            camera_position: Vec3::new(0.0, 0.0, 5.0),
+            shadow_map: None,
+        }
+    }
+
+    fn fragment_at(point: Vec3) -> Fragment {
+        let mut fragment = Fragment::new(0.0, 0.0, 0.5, point.normalize(), 1.0, point, point);
+        fragment.view_dir = Vec3::z();
+        fragment
+    }
+
+    #[test]
+    fn graphs_in_the_scene_file_reproduce_the_hand_written_shaders() {
+        let mut registry = MaterialRegistry::new();
+        registry.load_scene("assets/scene.txt").unwrap();
+        let lights = [Light::point(Vec3::new(3.0, 2.0, 6.0), Vec3::new(1.0, 0.96, 0.9), 1.1), Light::ambient(Vec3::new(0.6, 0.7, 1.0), 0.08)];
+        let noise = NoiseConfig::new(71).with_fractal(FractalType::FBm, Fractal::new(0.01, 5, 2.0, 0.5)).build();
+        let uniforms = uniforms_from(&lights, &noise);
+
+        let pairs: [(&str, &dyn Material); 2] = [("rocky_graph", &RockyMaterial::default()), ("icy_graph", &IcyMaterial::default())];
+        for (name, original) in pairs {
+            let graph = registry.get(name).unwrap();
+            for i in 0..64 {
+                let angle = i as f32 * 0.7;
+                let point = Vec3::new(angle.cos() * 0.4, (i as f32 * 0.31).sin() * 0.3, angle.sin() * 0.4);
+                let expected = original.shade(&fragment_at(point), &uniforms).to_vec3();
+                let actual = graph.shade(&fragment_at(point), &uniforms).to_vec3();
+                assert!((expected - actual).abs().max() < 1e-3, "{} en {:?}: {:?} != {:?}", name, point, actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn graphs_built_in_code_and_parsed_agree() {
+        let mut graph = ShaderGraph::new();
+        let position = graph.node(Node::Position);
+        let warped = graph.node(Node::Warp { input: position, amplitude: 0.1, scale: 300.0 });
+        let height = graph.node(Node::Ridged { input: warped, fractal: Fractal::new(500.0, 4, 2.0, 0.5) });
+        let low = graph.rgb(40, 60, 20);
+        let high = graph.rgb(200, 200, 210);
+        let color = graph.node(Node::Gradient { input: height, stops: vec![(0.2, low), (0.8, high)] });
+        graph.set_color(color);
+
+        let parsed = ShaderGraph::parse("
+            warped = warp position 0.1 300
+            height = ridged warped 500 4 2 0.5
+            low = rgb 40 60 20
+            high = rgb 200 200 210
+            surface = gradient height 0.2 low 0.8 high
+            color surface
+        ").unwrap();
+
+        let lights = [Light::ambient(Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let noise = Noise::default();
+        let uniforms = uniforms_from(&lights, &noise);
+        for point in [Vec3::new(0.1, 0.4, 0.2), Vec3::new(-0.3, 0.0, 0.35)] {
+            let a = graph.shade(&fragment_at(point), &uniforms).to_vec3();
+            let b = parsed.shade(&fragment_at(point), &uniforms).to_vec3();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let error = ShaderGraph::parse("a = noise position 10\nb = mix a missing 0.5\ncolor b").unwrap_err();
+        assert!(error.starts_with("línea 2"), "{}", error);
+        assert!(ShaderGraph::parse("a = rgb 1 2 3").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use nalgebra_glm::{Vec3, dot};
+use crate::fragment::Fragment;
+use crate::color::Color;
+use crate::utils::smoothstep;
+use crate::Uniforms;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LightKind {
+    // Luz puntual, como el sol en el centro del sistema
+    Point { position: Vec3 },
+    // Luz direccional; `direction` es hacia donde viaja la luz
+    Directional { direction: Vec3 },
+    // Luz ambiental, ilumina por igual sin importar la normal
+    Ambient,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn point(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Light { kind: LightKind::Point { position }, color, intensity }
+    }
+
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Light { kind: LightKind::Directional { direction: direction.normalize() }, color, intensity }
+    }
+
+    pub fn ambient(color: Vec3, intensity: f32) -> Self {
+        Light { kind: LightKind::Ambient, color, intensity }
+    }
+
+    // Dirección desde el punto en el mundo hacia la luz (None para la ambiental)
+    pub fn direction_from(&self, world_position: &Vec3) -> Option<Vec3> {
+        match self.kind {
+            LightKind::Point { position } => {
+                let to_light = position - world_position;
+                if to_light.magnitude() > f32::EPSILON {
+                    Some(to_light.normalize())
+                } else {
+                    None
+                }
+            }
+            LightKind::Directional { direction } => Some(-direction),
+            LightKind::Ambient => None,
+        }
+    }
+
+    // Energía de la luz reducida a un escalar según la luminancia de su color
+    pub fn radiance(&self) -> f32 {
+        (self.color.x * 0.2126 + self.color.y * 0.7152 + self.color.z * 0.0722) * self.intensity
+    }
+}
+
+// Parámetros especulares del material (Blinn-Phong)
+#[derive(Debug, Clone, Copy)]
+pub struct Specular {
+    pub shininess: f32,
+    pub color: Vec3,
+    pub strength: f32,
+}
+
+impl Specular {
+    pub const fn new(shininess: f32, color: Vec3, strength: f32) -> Self {
+        Specular { shininess, color, strength }
+    }
+
+    pub const fn matte() -> Self {
+        Specular { shininess: 1.0, color: Vec3::new(0.0, 0.0, 0.0), strength: 0.0 }
+    }
+}
+
+// Términos de iluminación acumulados de todas las luces
+#[derive(Debug, Clone, Copy)]
+pub struct Shading {
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+}
+
+impl Shading {
+    // Combina el color base con los términos; `specular_mask` limita dónde hay brillo
+    pub fn apply(&self, base_color: Color, specular_mask: f32) -> Color {
+        let base = base_color.to_vec3();
+        let lit = base.component_mul(&(self.ambient + self.diffuse)) + self.specular * specular_mask.clamp(0.0, 1.0);
+        Color::from_vec3(lit).with_alpha(base_color.alpha())
+    }
+}
+
+// Modelo Blinn-Phong en espacio de mundo; la normal se pasa aparte para poder perturbarla
+pub fn blinn_phong(fragment: &Fragment, normal: &Vec3, lights: &[Light], specular: &Specular) -> Shading {
+    let mut shading = Shading {
+        ambient: Vec3::zeros(),
+        diffuse: Vec3::zeros(),
+        specular: Vec3::zeros(),
+    };
+
+    let mut primary = true;
+
+    for light in lights {
+        let mut radiance = light.color * light.intensity;
+        match light.direction_from(&fragment.world_position) {
+            Some(direction) => {
+                // El mapa de sombras se genera desde la luz principal
+                if primary {
+                    radiance *= fragment.sunlight();
+                    primary = false;
+                }
+
+                let n_dot_l = dot(normal, &direction);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+                shading.diffuse += radiance * n_dot_l;
+
+                if specular.strength > 0.0 {
+                    let half_vector = (direction + fragment.view_dir).normalize();
+                    let n_dot_h = dot(normal, &half_vector).max(0.0);
+                    let highlight = n_dot_h.powf(specular.shininess) * specular.strength;
+                    shading.specular += radiance.component_mul(&specular.color) * highlight;
+                }
+            }
+            None => {
+                if let LightKind::Ambient = light.kind {
+                    shading.ambient += radiance;
+                }
+            }
+        }
+    }
+
+    shading
+}
+
+// Ilumina el fragmento en espacio de mundo: llena la dirección hacia la luz
+// principal (la primera no ambiental), la dirección hacia la cámara, la
+// sombra de la luz principal y la intensidad difusa acumulada de todas las luces
+pub fn light_fragment(fragment: &mut Fragment, uniforms: &Uniforms) {
+    let normal = fragment.normal;
+    let mut intensity = 0.0;
+    let mut light_dir = None;
+
+    fragment.shadow = uniforms.shadow_map
+        .map_or(1.0, |shadow_map| shadow_map.visibility(&fragment.world_position));
+
+    for light in uniforms.lights {
+        match light.direction_from(&fragment.world_position) {
+            Some(direction) => {
+                let visibility = if light_dir.is_none() { fragment.shadow } else { 1.0 };
+                intensity += dot(&normal, &direction).max(0.0) * light.radiance() * visibility;
+                light_dir.get_or_insert(direction);
+            }
+            None => {
+                if let LightKind::Ambient = light.kind {
+                    intensity += light.radiance();
+                }
+            }
+        }
+    }
+
+    fragment.intensity = intensity;
+    fragment.light_dir = light_dir.unwrap_or(normal);
+    fragment.view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+}
+
+// Qué tanto es de noche en el fragmento: 0 de día y 1 de noche, con una transición
+// suave de ancho `softness` (en coseno) alrededor del terminador. La sombra de otro
+// cuerpo también cuenta como noche; la de las nubes no
+pub fn night_side(fragment: &Fragment, softness: f32) -> f32 {
+    let n_dot_l = dot(&fragment.normal, &fragment.light_dir);
+    1.0 - smoothstep(-softness, softness, n_dot_l) * fragment.shadow
+}
@@ -1,7 +1,8 @@
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 use nalgebra_glm::{Vec3, Mat4, look_at, perspective, rotate_y};
-use utils::frustum_culling;
+use utils::{Frustum, bounding_radius};
 use std::time::{Duration, Instant};
+use std::sync::Arc;
 use std::f32::consts::PI;
 
 mod utils;
@@ -14,41 +15,233 @@ mod fragment;
 mod shaders;
 mod camera;
 mod spaceship;
+mod light;
+mod sun;
+mod shadow;
+mod tonemap;
+mod postprocess;
+mod skybox;
+mod atmosphere;
+mod noise;
+mod material;
+mod graph;
+mod bake;
+mod displacement;
+mod texture;
+mod clouds;
+mod cities;
+mod craters;
+mod biomes;
+mod ocean;
 
 
 use spaceship::Spaceship;
-use framebuffer::Framebuffer;
-use crate::fragment::Fragment;
-use crate::color::Color;
+use framebuffer::{BlendMode, Framebuffer};
 use vertex::Vertex;
 use obj::Obj;
-use triangle::triangle;
+use triangle::{clip_triangle, triangle};
 use camera::Camera;
-use shaders::{ring_shader, rocky_planet_shader, gas_giant_shader, gas_giant_shader2, volcanic_planet_shader, icy_planet_shader, desert_planet_shader, water_planet_shader, moon_shader, vertex_shader};
-use fastnoise_lite::{FastNoiseLite, NoiseType};
-
-pub struct Uniforms {
+use light::{Light, light_fragment};
+use sun::{draw_corona, SUN_CORONA};
+use shadow::{ShadowCache, ShadowMap};
+use tonemap::ToneMapping;
+use postprocess::{Effect, Lut, PostProcessing};
+use skybox::{Nebula, Skybox, StarfieldConfig};
+use atmosphere::{Atmosphere, BLUE_ATMOSPHERE, DUSTY_ATMOSPHERE, draw_atmosphere};
+use shaders::{vertex_shader, HullMaterial};
+use material::{Material, MaterialRegistry};
+use bake::{BakeLayout, BakedMaterial};
+use displacement::{subdivide, Displacement, Mountains, VertexProgram, Volcanoes};
+use texture::NormalMap;
+use clouds::{CloudMaterial, Overcast};
+use fastnoise_lite::{CellularDistanceFunction, CellularReturnType, DomainWarpType, FractalType};
+use noise::{Cellular, DomainWarp, Fractal, Noise, NoiseConfig};
+
+pub struct Uniforms<'a> {
     pub model_matrix: Mat4,
     pub view_matrix: Mat4,
     pub projection_matrix: Mat4,
     pub viewport_matrix: Mat4,
     pub time: f32,
-    pub noise: FastNoiseLite,
+    pub noise: &'a Noise,
+    pub lights: &'a [Light],
+    pub camera_position: Vec3,
+    pub shadow_map: Option<&'a ShadowMap>,
+}
+
+impl<'a> Uniforms<'a> {
+    // Sin cámara, luces ni tiempo: para evaluar materiales y programas en espacio de objeto
+    pub fn object_space(noise: &'a Noise) -> Self {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 0.0,
+            noise,
+            lights: &[],
+            camera_position: Vec3::zeros(),
+            shadow_map: None,
+        }
+    }
 }
 
 struct Planet {
     position: Vec3,
     scale: f32,
+    // Inclinación del eje (x, z) y fase inicial del giro (y)
     rotation: Vec3,
-    shader: fn(&Fragment, &Uniforms) -> Color,
+    material: Arc<dyn Material>,
     vertices: Vec<Vertex>,
+    bounding_radius: f32,
+    // Radio de la malla sin relieve; el horneado parte de esta esfera
+    base_radius: f32,
+    // Los cuerpos emisivos no reciben iluminación y dibujan una corona
+    emissive: bool,
+    casts_shadows: bool,
+    receives_shadows: bool,
+    orbit: Option<Orbit>,
+    blend_mode: BlendMode,
+    atmosphere: Option<Atmosphere>,
+    // Ruido propio del cuerpo, construido una sola vez
+    noise: Noise,
+    noise_config: NoiseConfig,
+    // Superficie horneada en segundo plano, si se pidió
+    bake: Option<Arc<BakedMaterial>>,
+    // Programa de vértices que depende del tiempo; los estáticos ya están aplicados a `vertices`
+    vertex_program: Option<Arc<dyn VertexProgram>>,
+    // Programa estático ya aplicado, para hornear la superficie a la misma altura que la malla
+    relief: Option<Arc<dyn VertexProgram>>,
+}
+
+// Órbita circular en el plano XZ alrededor de un punto fijo
+struct Orbit {
+    center: Vec3,
+    radius: f32,
+    speed: f32,
+}
+
+impl Planet {
+    fn new(position: Vec3, scale: f32, material: Arc<dyn Material>, vertices: Vec<Vertex>) -> Self {
+        let radius = bounding_radius(&vertices);
+        Planet {
+            position,
+            scale,
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+            material,
+            bounding_radius: radius,
+            base_radius: radius,
+            vertices,
+            emissive: false,
+            casts_shadows: true,
+            receives_shadows: true,
+            orbit: None,
+            blend_mode: BlendMode::Replace,
+            atmosphere: None,
+            noise: Noise::default(),
+            noise_config: NoiseConfig::default(),
+            bake: None,
+            vertex_program: None,
+            relief: None,
+        }
+    }
+
+    fn with_rotation(mut self, rotation: Vec3) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    fn emissive(mut self) -> Self {
+        self.emissive = true;
+        self.casts_shadows = false;
+        self.receives_shadows = false;
+        self
+    }
+
+    fn with_shadows(mut self, casts: bool, receives: bool) -> Self {
+        self.casts_shadows = casts;
+        self.receives_shadows = receives;
+        self
+    }
+
+    // Cuerpos transparentes: se dibujan al final, ordenados por distancia
+    fn with_blend(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    fn with_noise(mut self, config: NoiseConfig) -> Self {
+        self.noise = config.build();
+        self.noise_config = config;
+        self
+    }
+
+    // Subdivide la malla y le pasa el programa de vértices; va después de
+    // `with_noise` porque los desplazamientos usan el ruido del cuerpo
+    fn with_vertex_program(mut self, program: Arc<dyn VertexProgram>, subdivisions: u32) -> Self {
+        self.vertices = subdivide(&self.vertices, subdivisions);
+        if program.is_static() {
+            let uniforms = Uniforms::object_space(&self.noise);
+            self.vertices = self.vertices.iter().map(|vertex| program.process(vertex, &uniforms)).collect();
+            // El relieve sobresale de la esfera original
+            self.bounding_radius = bounding_radius(&self.vertices);
+            self.relief = Some(program);
+        } else {
+            self.vertex_program = Some(program);
+        }
+        self
+    }
+
+    // Empieza a hornear la superficie en otro hilo; los materiales que no se
+    // pueden hornear se quedan como están
+    fn bake(&mut self, layout: BakeLayout) {
+        if !self.material.bakeable() {
+            return;
+        }
+        let baked = Arc::new(BakedMaterial::spawn(self.material.clone(), self.noise_config, self.base_radius, self.relief.clone(), layout));
+        self.material = baked.clone();
+        self.bake = Some(baked);
+    }
+
+    fn with_atmosphere(mut self, atmosphere: Atmosphere) -> Self {
+        self.atmosphere = Some(atmosphere);
+        self
+    }
+
+    fn orbiting(mut self, center: Vec3, radius: f32, speed: f32) -> Self {
+        self.orbit = Some(Orbit { center, radius, speed });
+        self
+    }
+
+    fn position_at(&self, time: f32) -> Vec3 {
+        match &self.orbit {
+            Some(orbit) => {
+                let angle = time * orbit.speed;
+                orbit.center + Vec3::new(angle.cos(), 0.0, angle.sin()) * orbit.radius
+            }
+            None => self.position,
+        }
+    }
+
+    fn world_radius(&self) -> f32 {
+        self.bounding_radius * self.scale
+    }
+
+    // Traslación, inclinación del eje y giro sobre el eje inclinado
+    fn model_matrix(&self, time: f32) -> Mat4 {
+        Mat4::new_translation(&self.position_at(time))
+            * Mat4::from_axis_angle(&Vec3::x_axis(), self.rotation.x)
+            * Mat4::from_axis_angle(&Vec3::z_axis(), self.rotation.z)
+            * Mat4::from_axis_angle(&Vec3::y_axis(), time + self.rotation.y)
+            * Mat4::new_scaling(self.scale)
+    }
 }
 
 
-fn create_noise() -> FastNoiseLite {
-    let mut noise = FastNoiseLite::with_seed(1337);
-    noise.set_noise_type(Some(NoiseType::OpenSimplex2));
-    noise
+
+// Posición en el plano XZ a una distancia y ángulo del sol
+fn around_sun(distance: f32, angle: f32) -> Vec3 {
+    Vec3::new(distance * angle.cos(), 0.0, distance * angle.sin())
 }
 
 fn create_model_matrix(translation: Vec3, scale: f32, rotation_angle: f32) -> Mat4 {
@@ -64,7 +257,7 @@ fn create_view_matrix(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
 fn create_perspective_matrix(window_width: f32, window_height: f32) -> Mat4 {
     let fov = 45.0 * PI / 180.0;
     let aspect_ratio = window_width / window_height;
-    perspective(fov, aspect_ratio, 0.1, 1000.0)
+    perspective(aspect_ratio, fov, 0.1, 1000.0)
 }
 
 fn create_viewport_matrix(width: f32, height: f32) -> Mat4 {
@@ -79,7 +272,176 @@ fn create_viewport_matrix(width: f32, height: f32) -> Mat4 {
 
 
 
+// Todo lo que se dibuja en un cuadro
+struct Scene {
+    planets: Vec<Planet>,
+    spaceship: Spaceship,
+    lights: Vec<Light>,
+    sun_position: Vec3,
+    skybox: Skybox,
+    // Ruido de la nave y de la corona
+    noise: Noise,
+}
+
+fn build_scene(bake: Option<BakeLayout>) -> Scene {
+    let obj = Obj::load("assets/models/sphere2.obj").expect("Error al cargar el modelo");
+    let ring_obj = Obj::load("assets/models/ring1.obj").expect("Error al cargar el modelo del aro");
+    let sun_position = Vec3::new(0.0, 0.0, 0.0);
+    // Cada planeta en un ángulo distinto alrededor del sol para que no queden
+    // todos alineados, cada uno a la sombra del anterior
+    let planet1_position = around_sun(2.0, 0.0);
+    let giant_position = around_sun(4.0, 1.2);
+    // Inclinación compartida por el gigante gaseoso y su aro
+    let ring_tilt = Vec3::new(-0.45, 0.0, 0.15);
+
+    // Los grafos del archivo de escena se suman a los materiales de siempre
+    let mut materials = MaterialRegistry::with_defaults();
+    match NormalMap::load("assets/textures/hull_normal.png") {
+        Ok(map) => materials.register("spaceship", HullMaterial { normal_map: Some(Arc::new(map)), ..HullMaterial::default() }),
+        Err(error) => eprintln!("Casco sin mapa de normales: {}", error),
+    }
+    if let Err(error) = materials.load_scene("assets/scene.txt") {
+        eprintln!("Sin materiales de la escena: {}", error);
+    }
+    let material = |name: &str| materials.get(name).unwrap_or_else(|| panic!("Material desconocido: {} (disponibles: {})", name, materials.names().join(", ")));
+
+    // Deformación suave para dunas y corrientes
+    let swirl = DomainWarp { warp_type: DomainWarpType::OpenSimplex2, amplitude: 30.0, frequency: 0.01, octaves: 1 };
+
+    let water_position = around_sun(10.0, 5.3);
+    let water_noise = NoiseConfig::new(53).with_domain_warp(swirl);
+    let clouds = Arc::new(CloudMaterial::default());
+    // Biomas de la escena; sin ellos queda el océano con islas
+    let habitable = materials.get("earth").unwrap_or_else(|| material("habitable"));
+    // Inclinación del eje como la de la Tierra: los casquetes y las nubes giran con él
+    let earth_tilt = Vec3::new(0.41, 0.0, 0.0);
+
+    let mut planets = vec![
+        //Sol
+        Planet::new(sun_position, 1.8, material("sun"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(11))
+            .emissive(),
+        //planeta 1
+        Planet::new(planet1_position, 0.5, material("desert"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(23).with_domain_warp(swirl))
+            .with_atmosphere(DUSTY_ATMOSPHERE),
+        //Planeta 2 con Aro
+        Planet::new(giant_position, 0.8, material("ring"), ring_obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(31))
+            .with_rotation(ring_tilt)
+            .with_blend(BlendMode::Alpha),
+        Planet::new(giant_position, 0.6, material("gas_giant2"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(37).with_fractal(FractalType::FBm, Fractal::new(0.01, 3, 2.0, 0.5)))
+            .with_rotation(ring_tilt),
+        //Planeta 3
+        Planet::new(around_sun(6.0, 2.6), 0.6, material("volcanic"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(41).with_fractal(FractalType::FBm, Fractal::new(0.01, 4, 2.0, 0.5)))
+            .with_vertex_program(Arc::new(Displacement::new(Volcanoes::default())), 2),
+        //Planeta 4
+        Planet::new(around_sun(8.0, 4.0), 0.6, material("gas_giant"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(43)),
+        //Planeta 5, con nubes que le dan sombra
+        Planet::new(water_position, 0.6, Arc::new(Overcast::new(habitable, clouds.clone())), obj.get_vertex_array())
+            .with_noise(water_noise)
+            .with_rotation(earth_tilt)
+            .with_atmosphere(BLUE_ATMOSPHERE),
+        // La capa de nubes usa el mismo ruido para que las sombras caigan bajo cada nube
+        Planet::new(water_position, 0.6 * (1.0 + clouds.altitude), clouds.clone(), obj.get_vertex_array())
+            .with_noise(water_noise)
+            .with_rotation(earth_tilt)
+            .with_shadows(false, true)
+            .with_blend(BlendMode::Alpha),
+        //Planeta 6
+        Planet::new(around_sun(12.0, 0.7), 0.6, material("icy"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(61).with_fractal(FractalType::FBm, Fractal::new(0.01, 3, 2.2, 0.45))),
+        //Planeta 7
+        Planet::new(around_sun(14.0, 2.0), 0.6, material("rocky"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(71).with_fractal(FractalType::FBm, Fractal::new(0.01, 5, 2.0, 0.5)))
+            .with_vertex_program(Arc::new(Displacement::new(Mountains::default())), 2),
+        //Planeta 8: mismo shader rocoso, con placas celulares en vez de ruido suave
+        Planet::new(around_sun(16.0, 3.4), 0.5, material("rocky"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(89).with_frequency(0.004).cellular(Cellular {
+                distance: CellularDistanceFunction::Euclidean,
+                return_type: CellularReturnType::CellValue,
+                jitter: 0.9,
+            })),
+        //Luna del planeta 1, su órbita pasa entre el sol y el planeta
+        Planet::new(planet1_position, 0.2, material("moon"), obj.get_vertex_array())
+            .with_noise(NoiseConfig::new(97))
+            .orbiting(planet1_position, 0.7, 0.4),
+    ];
+
+    if let Some(layout) = bake {
+        for planet in &mut planets {
+            planet.bake(layout);
+        }
+    }
+
+
+    let spaceship_obj = Obj::load("assets/models/mini_espacioship.obj").expect("Error al cargar el modelo de la nave espacial");
+
+    let spaceship = Spaceship::new(
+        Vec3::new(14.0, 0.0, 18.0), // Posición inicial
+        Vec3::new(0.0, 0.0, 0.0), // Rotación inicial
+        1.0,                      // Escala
+        spaceship_obj.get_vertex_array(), 
+        material("spaceship"),
+    );
+    // Luces de la escena: el sol como luz puntual y una luz ambiental tenue
+    let lights = vec![
+        Light::point(sun_position, Vec3::new(1.0, 0.96, 0.9), 1.1),
+        Light::ambient(Vec3::new(0.6, 0.7, 1.0), 0.08),
+    ];
+
+    // Cielo de estrellas y nebulosa, horneado una vez al iniciar
+    let skybox = Skybox::new(&StarfieldConfig::default())
+        .with_nebula(Nebula::default())
+        .baked(512);
+
+    Scene { planets, spaceship, lights, sun_position, skybox, noise: Noise::default() }
+}
+
+impl Scene {
+    fn wait_for_bakes(&self) {
+        for bake in self.planets.iter().filter_map(|planet| planet.bake.as_ref()) {
+            bake.wait();
+        }
+    }
+}
+
+// Cadena de postproceso por defecto; la gradación se omite si no se encuentra la tabla
+fn build_post_processing() -> PostProcessing {
+    let post_processing = PostProcessing::new()
+        .with(Effect::Bloom { threshold: 1.0, intensity: 0.8, radius: 8 })
+        .with(Effect::ChromaticAberration { strength: 2.0 })
+        .with(Effect::Vignette { strength: 0.5, radius: 0.45 })
+        .with(Effect::FilmGrain { amount: 0.025 });
+
+    match Lut::load("assets/luts/cinematic.png") {
+        Ok(lut) => post_processing.with(Effect::ColorGrading { lut, strength: 1.0 }),
+        Err(error) => {
+            eprintln!("Sin gradación de color: {}", error);
+            post_processing
+        }
+    }
+}
+
+// Teclas para alternar cada pasada de postproceso
+const POST_PROCESSING_KEYS: [(Key, &str); 5] = [
+    (Key::F1, "bloom"),
+    (Key::F2, "aberration"),
+    (Key::F3, "vignette"),
+    (Key::F4, "grain"),
+    (Key::F5, "lut"),
+];
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        run_headless(&args);
+        return;
+    }
+
     let window_width = 800;
     let window_height = 600;
     let framebuffer_width = 800;
@@ -88,218 +450,357 @@ fn main() {
     let start_time = Instant::now();
 
     let mut framebuffer = Framebuffer::new(framebuffer_width, framebuffer_height);
+    let mut shadows = ShadowCache::new(256);
+    // A-buffer para capas transparentes que se cruzan (aro y atmósferas); se alterna con O
+    framebuffer.enable_a_buffer(8);
     let mut window = Window::new("Proyecto 3 - GPC", window_width, window_height, WindowOptions::default())
         .unwrap();
 
     window.set_position(500, 500);
-    framebuffer.set_background_color(0x333355);
-
-
-
-    let obj = Obj::load("assets/models/sphere2.obj").expect("Error al cargar el modelo");
-    let ring_obj = Obj::load("assets/models/ring1.obj").expect("Error al cargar el modelo del aro");
-
-    
-    let planets = vec![
-    //planeta 1
-    Planet {
-        position: Vec3::new(2.0, 0.0, 0.0),
-        scale: 0.5,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: desert_planet_shader,
-        vertices: obj.get_vertex_array(),
-    },
-    //Planeta 2 con Aro
-    Planet {
-        position: Vec3::new(4.0, 0.0, 0.0),
-        scale: 0.8,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: ring_shader,
-        vertices: ring_obj.get_vertex_array(),
-    },
-    Planet {
-        position: Vec3::new(4.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: gas_giant_shader2,
-        vertices: obj.get_vertex_array(),
-    },
-    //Planeta 3
-    Planet {
-        position: Vec3::new(6.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: volcanic_planet_shader,
-        vertices: obj.get_vertex_array(),
-    },
-    //Planeta 4
-    Planet {
-        position: Vec3::new(8.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: gas_giant_shader,
-        vertices: obj.get_vertex_array(),
-    },    
-    //Planeta 5
-    Planet {
-        position: Vec3::new(10.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: water_planet_shader,
-        vertices: obj.get_vertex_array(),
-    },
-    //Planeta 6
-    Planet {
-        position: Vec3::new(12.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: icy_planet_shader,
-        vertices: obj.get_vertex_array(),
-    },
-    //Planeta 6
-    Planet {
-        position: Vec3::new(14.0, 0.0, 0.0),
-        scale: 0.6,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: rocky_planet_shader,
-        vertices: obj.get_vertex_array(),
-    },
-    //Luna del planeta 1
-    Planet {
-        position: Vec3::new(2.0, 0.0, 2.0),
-        scale: 0.2,
-        rotation: Vec3::new(0.0, 0.0, 0.0),
-        shader: moon_shader,
-        vertices: obj.get_vertex_array(),
-    },
-];
-
+    framebuffer.set_background_color(0x000000);
 
-    let spaceship_obj = Obj::load("assets/models/mini_espacioship.obj").expect("Error al cargar el modelo de la nave espacial");
+    // Con --bake las superficies se hornean en segundo plano mientras corre la ventana
+    let mut scene = build_scene(bake_option(&args));
+    if let Some(directory) = arg_value(&args, "--save-bakes") {
+        save_bakes(&scene, directory);
+    }
 
-    let mut spaceship = Spaceship::new(
-        Vec3::new(14.0, 0.0, 18.0), // Posición inicial
-        Vec3::new(0.0, 0.0, 0.0), // Rotación inicial
-        1.0,                      // Escala
-        spaceship_obj.get_vertex_array(), 
-        desert_planet_shader,
-    );
     let mut camera = Camera::new(
         Vec3::new(0.0, 10.0, 20.0),
         Vec3::new(0.0, 10.0, 0.0),
         Vec3::new(0.0, 1.0, 0.0),
     );
 
-    
+    let mut tone_mapping = ToneMapping::default();
+    let mut post_processing = build_post_processing();
 
-    
     while window.is_open() {
         if window.is_key_down(Key::Escape) {
             break;
         }
     
-        handle_input(&window, &mut spaceship, &mut camera);
-        update_camera(&mut camera, &spaceship);
-    
-        framebuffer.clear();
-    
-        let time_elapsed = start_time.elapsed().as_secs_f32();
-        let view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
-        let projection_matrix = create_perspective_matrix(window_width as f32 *0.5, window_height as f32 *0.5);
-        let viewport_matrix = create_viewport_matrix(framebuffer_width as f32, framebuffer_height as f32);
-    
-        for planet in &planets {
-            // **Aplicar frustum culling**: verificar si el planeta está dentro del frustum
-            if !frustum_culling(
-                &planet.position,
-                planet.scale,
-                &view_matrix,
-                &projection_matrix,
-            ) {
-                continue; // Saltar este planeta si está fuera del frustum
-            }
-    
-            // Calcular matriz de modelo del planeta
-            let model_matrix = create_model_matrix(planet.position, planet.scale, time_elapsed);
-    
-            // Las matrices de vista, proyección y viewport son constantes para todos los planetas
-            let uniforms = Uniforms {
-                model_matrix,
-                view_matrix,
-                projection_matrix,
-                viewport_matrix,
-                time: time_elapsed,
-                noise: create_noise(),
-            };
-    
-            // Transformar los vértices del planeta en función de sus propias matrices
-            let transformed_vertices = planet.vertices.iter()
-                .map(|vertex| vertex_shader(vertex, &uniforms))
-                .collect::<Vec<_>>();
-    
-            // Dividir en triángulos
-            let triangles = transformed_vertices.chunks(3)
-                .filter(|tri| tri.len() == 3)
-                .map(|tri| [tri[0].clone(), tri[1].clone(), tri[2].clone()])
-                .collect::<Vec<_>>();
-    
-            let mut fragments = Vec::new();
-    
-            // Generar fragmentos para rasterizar
-            for tri in &triangles {
-                fragments.extend(triangle(&tri[0], &tri[1], &tri[2]));
+        handle_input(&window, &mut scene.spaceship, &mut camera);
+        update_camera(&mut camera, &scene.spaceship);
+
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            if framebuffer.a_buffer_enabled() {
+                framebuffer.disable_a_buffer();
+            } else {
+                framebuffer.enable_a_buffer(8);
             }
-    
-            // Dibujar los fragmentos en el framebuffer
-            for fragment in fragments {
-                let (x, y) = (fragment.position.x as usize, fragment.position.y as usize);
-                if x < framebuffer.width && y < framebuffer.height {
-                    let shaded_color = (planet.shader)(&fragment, &uniforms);
-                    framebuffer.set_current_color(shaded_color.to_hex());
-                    framebuffer.point(x, y, fragment.depth);
-                }
+        }
+
+        // T cambia el operador de tonos, +/- ajustan la exposición
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            tone_mapping.operator = tone_mapping.operator.next();
+        }
+        if window.is_key_down(Key::Equal) {
+            tone_mapping.exposure *= 1.02;
+        }
+        if window.is_key_down(Key::Minus) {
+            tone_mapping.exposure /= 1.02;
+        }
+
+        for (key, pass) in POST_PROCESSING_KEYS {
+            if window.is_key_pressed(key, KeyRepeat::No) {
+                post_processing.toggle(pass);
             }
         }
     
-        
-        let model_matrix = create_model_matrix(spaceship.position, spaceship.scale, spaceship.rotation.y);
+        let time_elapsed = start_time.elapsed().as_secs_f32();
+        let culled = render_frame(&mut framebuffer, &mut shadows, &scene, &camera, time_elapsed);
+
+        // De HDR lineal a colores de pantalla, con las pasadas de postproceso activas
+        post_processing.apply(&mut framebuffer, &tone_mapping, time_elapsed);
     
+        window.set_title(&format!(
+            "Proyecto 3 - GPC | descartados: {}/{} | {:?} x{:.2} | {}",
+            culled,
+            scene.planets.len(),
+            tone_mapping.operator,
+            tone_mapping.exposure,
+            post_processing.enabled_names().join(" "),
+        ));
+        window.update_with_buffer(&framebuffer.buffer, framebuffer_width, framebuffer_height).unwrap();
+        std::thread::sleep(frame_delay);
+    }
+    
+}
+
+// Valor que sigue a una opción de la línea de comandos, si no es otra opción
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .filter(|value| !value.starts_with("--"))
+        .map(String::as_str)
+}
+
+// Vector escrito como "x,y,z"
+fn parse_vec3(value: &str) -> Option<Vec3> {
+    let components: Vec<f32> = value.split(',').map(|c| c.trim().parse().ok()).collect::<Option<_>>()?;
+    match components[..] {
+        [x, y, z] => Some(Vec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+// --bake equirect[:ancho] | cube[:lado] hornea las superficies de los planetas
+fn bake_option(args: &[String]) -> Option<BakeLayout> {
+    let value = arg_value(args, "--bake")?;
+    let layout = BakeLayout::parse(value);
+    if layout.is_none() {
+        eprintln!("Horneado desconocido '{}', se usa el sombreado procedural", value);
+    }
+    layout
+}
+
+// Espera los horneados y guarda los mapas de cada planeta en el directorio
+fn save_bakes(scene: &Scene, directory: &str) {
+    if let Err(error) = std::fs::create_dir_all(directory) {
+        eprintln!("No se pudo crear {}: {}", directory, error);
+        return;
+    }
+    for (index, planet) in scene.planets.iter().enumerate() {
+        let Some(map) = planet.bake.as_ref().and_then(|bake| bake.wait()) else {
+            continue;
+        };
+        let prefix = format!("{}/planet{}", directory.trim_end_matches('/'), index);
+        match map.save_png(&prefix) {
+            Ok(()) => println!("{}_*.png", prefix),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
+
+// Renderiza un solo cuadro sin ventana y lo guarda como PNG:
+//   --headless [salida.png] [--time s] [--eye x,y,z] [--look x,y,z] [--no-<pasada>]...
+//   [--bake equirect|cube[:resolución]] [--save-bakes directorio]
+fn run_headless(args: &[String]) {
+    let output = arg_value(args, "--headless").unwrap_or("render.png");
+    let time = arg_value(args, "--time")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.0);
+
+    let mut framebuffer = Framebuffer::new(800, 600);
+    let mut shadows = ShadowCache::new(256);
+    framebuffer.enable_a_buffer(8);
+    framebuffer.set_background_color(0x000000);
+
+    let scene = build_scene(bake_option(args));
+    // Sin ventana no hay cuadros de espera: se renderiza con los mapas terminados
+    match arg_value(args, "--save-bakes") {
+        Some(directory) => save_bakes(&scene, directory),
+        None => scene.wait_for_bakes(),
+    }
+    let mut camera = Camera::new(
+        Vec3::new(0.0, 10.0, 20.0),
+        Vec3::new(0.0, 10.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    update_camera(&mut camera, &scene.spaceship);
+    // Sin estas opciones se usa la vista inicial detrás de la nave
+    if let Some(eye) = arg_value(args, "--eye").and_then(parse_vec3) {
+        camera.eye = eye;
+    }
+    if let Some(center) = arg_value(args, "--look").and_then(parse_vec3) {
+        camera.center = center;
+    }
+
+    let mut post_processing = build_post_processing();
+    for arg in args {
+        if let Some(pass) = arg.strip_prefix("--no-") {
+            post_processing.set_enabled(pass, false);
+        }
+    }
+
+    render_frame(&mut framebuffer, &mut shadows, &scene, &camera, time);
+    post_processing.apply(&mut framebuffer, &ToneMapping::default(), time);
+
+    let mut image = image::RgbImage::new(framebuffer.width as u32, framebuffer.height as u32);
+    for (pixel, color) in image.pixels_mut().zip(&framebuffer.buffer) {
+        *pixel = image::Rgb([(color >> 16) as u8, (color >> 8) as u8, *color as u8]);
+    }
+    image.save(output).expect("Error al guardar la imagen");
+    println!("{} ({})", output, post_processing.enabled_names().join(" "));
+}
+
+// Dibuja la escena en HDR y devuelve cuántos cuerpos quedaron fuera del frustum
+fn render_frame(framebuffer: &mut Framebuffer, shadows: &mut ShadowCache, scene: &Scene, camera: &Camera, time_elapsed: f32) -> usize {
+    framebuffer.clear();
+
+    let planets = &scene.planets;
+    let spaceship = &scene.spaceship;
+    let view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
+    let projection_matrix = create_perspective_matrix(framebuffer.width as f32, framebuffer.height as f32);
+    let viewport_matrix = create_viewport_matrix(framebuffer.width as f32, framebuffer.height as f32);
+    let frustum = Frustum::new(&view_matrix, &projection_matrix);
+    let mut culled = 0;
+
+    scene.skybox.draw(framebuffer, &view_matrix, &projection_matrix);
+
+    // Dibuja un planeta con su mapa de sombras y su modo de mezcla
+    let draw_planet = |framebuffer: &mut Framebuffer, shadows: &mut ShadowCache, (index, planet): (usize, &Planet)| {
+        // Sombras: mapa de profundidad desde el sol encuadrado sobre este planeta
+        let shadow = if planet.receives_shadows {
+            Some(render_shadow_map(shadows, planets, index, &scene.sun_position, time_elapsed))
+        } else {
+            None
+        };
+
+        // Las matrices de vista, proyección y viewport son constantes para todos los planetas
         let uniforms = Uniforms {
-            model_matrix,
+            model_matrix: planet.model_matrix(time_elapsed),
             view_matrix,
             projection_matrix,
             viewport_matrix,
             time: time_elapsed,
-            noise: create_noise(),
+            noise: &planet.noise,
+            lights: &scene.lights,
+            camera_position: camera.eye,
+            shadow_map: shadow,
         };
-    
-        let transformed_vertices = spaceship.vertices.iter()
-            .map(|vertex| vertex_shader(vertex, &uniforms))
-            .collect::<Vec<_>>();
-    
-        let triangles = transformed_vertices.chunks(3)
-            .filter(|tri| tri.len() == 3)
-            .map(|tri| [tri[0].clone(), tri[1].clone(), tri[2].clone()])
-            .collect::<Vec<_>>();
-    
-        let mut fragments = Vec::new();
-        for tri in &triangles {
-            fragments.extend(triangle(&tri[0], &tri[1], &tri[2]));
+
+        // Los transparentes se mezclan sin escribir profundidad
+        framebuffer.set_blend_mode(planet.blend_mode);
+        framebuffer.set_depth_write(planet.blend_mode == BlendMode::Replace);
+        render(framebuffer, &uniforms, &planet.vertices, planet.vertex_program.as_deref(), planet.material.as_ref(), planet.emissive);
+        framebuffer.set_blend_mode(BlendMode::Replace);
+        framebuffer.set_depth_write(true);
+    };
+
+    let mut visible = Vec::new();
+    for (index, planet) in planets.iter().enumerate() {
+        // **Aplicar frustum culling**: esfera envolvente en espacio de mundo contra los planos del frustum
+        if !frustum.intersects_sphere(&planet.position_at(time_elapsed), planet.world_radius()) {
+            culled += 1;
+            continue; // Saltar este planeta si está fuera del frustum
         }
-    
-        for fragment in fragments {
-            let (x, y) = (fragment.position.x as usize, fragment.position.y as usize);
-            if x < framebuffer.width && y < framebuffer.height {
-                let shaded_color = (spaceship.shader)(&fragment, &uniforms);
-                framebuffer.set_current_color(shaded_color.to_hex());
-                framebuffer.point(x, y, fragment.depth);
-            }
+        visible.push((index, planet));
+    }
+
+    // Primero los opacos; los transparentes se ordenan de atrás hacia adelante
+    let (opaque, mut transparent): (Vec<_>, Vec<_>) = visible.into_iter()
+        .partition(|(_, planet)| planet.blend_mode == BlendMode::Replace);
+    let camera_distance = |planet: &Planet| (planet.position_at(time_elapsed) - camera.eye).magnitude();
+    transparent.sort_by(|(_, a), (_, b)| camera_distance(b).total_cmp(&camera_distance(a)));
+
+    for &planet in &opaque {
+        draw_planet(framebuffer, shadows, planet);
+    }
+
+    let model_matrix = create_model_matrix(spaceship.position, spaceship.scale, spaceship.rotation.y);
+
+    let uniforms = Uniforms {
+        model_matrix,
+        view_matrix,
+        projection_matrix,
+        viewport_matrix,
+        time: time_elapsed,
+        noise: &scene.noise,
+        lights: &scene.lights,
+        camera_position: camera.eye,
+        shadow_map: None,
+    };
+
+    render(framebuffer, &uniforms, &spaceship.vertices, None, spaceship.material.as_ref(), false);
+
+    for &planet in &transparent {
+        draw_planet(framebuffer, shadows, planet);
+    }
+
+    // Atmósferas, con la esfera exterior completa para el descarte
+    for planet in planets {
+        let Some(atmosphere) = &planet.atmosphere else {
+            continue;
+        };
+        let center = planet.position_at(time_elapsed);
+        if frustum.intersects_sphere(&center, planet.world_radius() * (1.0 + atmosphere.thickness)) {
+            draw_atmosphere(framebuffer, atmosphere, &center, planet.world_radius(), &uniforms);
         }
-    
-        window.update_with_buffer(&framebuffer.buffer, framebuffer_width, framebuffer_height).unwrap();
-        std::thread::sleep(frame_delay);
     }
-    
+
+    // Corona de los cuerpos emisivos, después de la geometría para respetar la profundidad
+    for planet in planets.iter().filter(|planet| planet.emissive) {
+        draw_corona(framebuffer, &SUN_CORONA, &planet.position_at(time_elapsed), planet.world_radius(), &uniforms);
+    }
+
+    // Con A-buffer, las capas transparentes se ordenan por pixel y se mezclan aquí
+    framebuffer.resolve_transparency();
+
+    culled
+}
+
+
+// Índice en `Scene::lights` del sol, la única luz que proyecta sombras
+const SUN_LIGHT: usize = 0;
+
+// Mapa de sombras del sol sobre el receptor, dibujando de nuevo los cuerpos que pueden
+// taparle la luz solo si alguno se movió desde el cuadro anterior
+fn render_shadow_map<'a>(shadows: &'a mut ShadowCache, planets: &[Planet], receiver: usize, light_position: &Vec3, time: f32) -> &'a ShadowMap {
+    // El propio receptor ya queda oscuro por su término difuso
+    let casters = planets.iter().enumerate()
+        .filter(|&(index, caster)| index != receiver && caster.casts_shadows)
+        .map(|(index, caster)| (index, caster.position_at(time), caster.world_radius()))
+        .collect::<Vec<_>>();
+    let target = &planets[receiver];
+
+    shadows.map((SUN_LIGHT, receiver), light_position, &target.position_at(time), target.world_radius(), &casters, |shadow_map, indices| {
+        for &index in indices {
+            let caster = &planets[index];
+            // Los cuerpos transparentes solo tapan la luz donde su superficie es opaca
+            let opaque = caster.blend_mode == BlendMode::Replace;
+            let uniforms = Uniforms { time, ..Uniforms::object_space(&caster.noise) };
+            shadow_map.render_caster(&caster.vertices, &caster.model_matrix(time), |fragment| {
+                if opaque { 1.0 } else { caster.material.surface(fragment, &uniforms).albedo.alpha() }
+            });
+        }
+    })
+}
+
+// Transforma, rasteriza, ilumina y sombrea una malla sobre el framebuffer
+fn render(framebuffer: &mut Framebuffer, uniforms: &Uniforms, vertex_array: &[Vertex], vertex_program: Option<&dyn VertexProgram>, material: &dyn Material, emissive: bool) {
+    // Programa propio del objeto en espacio de objeto
+    let object_vertices = vertex_array.iter()
+        .map(|vertex| match vertex_program {
+            Some(program) => program.process(vertex, uniforms),
+            None => vertex.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    // Recortar contra los planos cercano y lejano antes de proyectar (detrás de la cámara
+    // la proyección se invierte) y luego aplicar las matrices del objeto a cada pedazo
+    let clip_matrix = uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix;
+    let triangles = object_vertices.chunks_exact(3)
+        .flat_map(|tri| clip_triangle([&tri[0], &tri[1], &tri[2]], &clip_matrix))
+        .map(|tri| tri.map(|vertex| vertex_shader(&vertex, uniforms)))
+        .collect::<Vec<_>>();
+
+    // Con mezcla activa, los triángulos del propio objeto también van de atrás hacia adelante
+    // (no hace falta si el A-buffer ordena por pixel)
+    let mut triangles = triangles;
+    if framebuffer.blend_mode() != BlendMode::Replace && !framebuffer.a_buffer_enabled() {
+        let depth = |tri: &[Vertex; 3]| tri.iter().map(|vertex| vertex.transformed_position.z).sum::<f32>();
+        triangles.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
+    }
+
+    // Generar fragmentos para rasterizar, solo dentro de la región visible
+    let clip = framebuffer.clip_rect();
+    let mut fragments = Vec::new();
+    for tri in &triangles {
+        fragments.extend(triangle(&tri[0], &tri[1], &tri[2], &clip));
+    }
+
+    // Iluminar en espacio de mundo, sombrear y dibujar los fragmentos
+    for mut fragment in fragments {
+        if !emissive {
+            light_fragment(&mut fragment, uniforms);
+        }
+        let (x, y) = (fragment.position.x as usize, fragment.position.y as usize);
+        let shaded_color = material.shade(&fragment, uniforms);
+        framebuffer.set_current_color(shaded_color);
+        framebuffer.point(x, y, fragment.depth);
+    }
 }
 
 
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use nalgebra_glm::Vec3;
+use crate::biomes::{BiomeMaterial, BiomeTable};
+use crate::color::Color;
+use crate::fragment::Fragment;
+use crate::graph::ShaderGraph;
+use crate::shaders::{
+    DesertMaterial, GasGiantMaterial, HullMaterial, IcyMaterial, MoonMaterial, RingMaterial, RockyMaterial,
+    SunMaterial, VolcanicMaterial, WaterMaterial,
+};
+use crate::Uniforms;
+
+// Lo que un material sabe de un punto sin mirar la luz ni el reloj: se puede hornear
+#[derive(Debug, Clone, Copy)]
+pub struct Surface {
+    // Color base lineal; el alfa es la opacidad
+    pub albedo: Color,
+    pub specular_mask: f32,
+    // Luz propia en HDR, se suma después de iluminar
+    pub emission: Vec3,
+    // Máscara de la capa animada de cada material (lava, grietas con agua), 0 donde no hay
+    pub layer: f32,
+    // Luz propia que solo se ve del lado nocturno (ciudades), en HDR
+    pub night: Vec3,
+}
+
+impl Surface {
+    pub fn new(albedo: Color) -> Self {
+        Surface { albedo, specular_mask: 1.0, emission: Vec3::zeros(), layer: 0.0, night: Vec3::zeros() }
+    }
+
+    pub fn with_specular_mask(mut self, specular_mask: f32) -> Self {
+        self.specular_mask = specular_mask;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Vec3) -> Self {
+        self.emission = emission;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: f32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_night(mut self, night: Vec3) -> Self {
+        self.night = night;
+        self
+    }
+
+    // Interpolación de todos los canales, para filtrar mapas horneados
+    pub fn lerp(&self, other: &Surface, t: f32) -> Self {
+        Surface {
+            albedo: self.albedo.lerp(&other.albedo, t),
+            specular_mask: self.specular_mask + (other.specular_mask - self.specular_mask) * t,
+            emission: self.emission + (other.emission - self.emission) * t,
+            layer: self.layer + (other.layer - self.layer) * t,
+            night: self.night + (other.night - self.night) * t,
+        }
+    }
+}
+
+// Sombreado en dos etapas con sus propios parámetros (paletas, escalas, umbrales):
+// la superficie depende solo de la posición en el modelo, la luz agrega
+// iluminación y capas animadas encima
+pub trait Material: Send + Sync {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface;
+
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color;
+
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let surface = self.surface(fragment, uniforms);
+        self.light(&surface, fragment, uniforms)
+    }
+
+    // Falso si la superficie no es función de la dirección sobre una esfera
+    // (el aro) o si cambia con el tiempo o la vista (el sol)
+    fn bakeable(&self) -> bool {
+        true
+    }
+}
+
+// Materiales disponibles por nombre, para armar la escena sin tocar los shaders
+#[derive(Default)]
+pub struct MaterialRegistry {
+    materials: HashMap<String, Arc<dyn Material>>,
+}
+
+impl MaterialRegistry {
+    pub fn new() -> Self {
+        MaterialRegistry::default()
+    }
+
+    // Los materiales de la escena con el aspecto de siempre
+    pub fn with_defaults() -> Self {
+        let mut registry = MaterialRegistry::new();
+        registry.register("sun", SunMaterial::default());
+        registry.register("rocky", RockyMaterial::default());
+        registry.register("volcanic", VolcanicMaterial::default());
+        registry.register("icy", IcyMaterial::default());
+        registry.register("desert", DesertMaterial::default());
+        registry.register("water", WaterMaterial::default());
+        registry.register("habitable", WaterMaterial::habitable());
+        registry.register("moon", MoonMaterial::default());
+        registry.register("gas_giant", GasGiantMaterial::default());
+        registry.register("gas_giant2", GasGiantMaterial::violet());
+        registry.register("ring", RingMaterial::default());
+        registry.register("spaceship", HullMaterial::default());
+        registry
+    }
+
+    // Reemplaza el material si ya había uno con ese nombre
+    pub fn register(&mut self, name: &str, material: impl Material + 'static) {
+        self.materials.insert(name.to_string(), Arc::new(material));
+    }
+
+    // Registra los bloques del archivo de escena: `material <nombre>` ... `end` es un
+    // grafo y `biomes <nombre>` ... `end` la tabla de un planeta con biomas
+    pub fn load_scene(&mut self, path: &str) -> Result<(), String> {
+        let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut block: Option<(&str, String, usize, String)> = None;
+
+        for (number, line) in source.lines().enumerate() {
+            let words = line.split('#').next().unwrap_or("").split_whitespace().collect::<Vec<_>>();
+            match (words.as_slice(), &mut block) {
+                (["material", name], None) => block = Some(("material", name.to_string(), number + 1, String::new())),
+                (["biomes", name], None) => block = Some(("biomes", name.to_string(), number + 1, String::new())),
+                (["end"], Some(_)) => {
+                    let (kind, name, start, body) = block.take().unwrap_or_default();
+                    // Las líneas del error se cuentan desde la del bloque
+                    let context = |e: String| format!("{}: {} '{}' (línea {}): {}", path, kind, name, start, e);
+                    if kind == "biomes" {
+                        let table = BiomeTable::parse(&body).map_err(context)?;
+                        self.register(&name, BiomeMaterial::new(table));
+                    } else {
+                        let graph = ShaderGraph::parse(&body).map_err(context)?;
+                        self.register(&name, graph);
+                    }
+                }
+                ([], None) => {}
+                (_, Some((_, _, _, body))) => {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+                (_, None) => {
+                    return Err(format!("{}: línea {}: se esperaba 'material <nombre>' o 'biomes <nombre>'", path, number + 1))
+                }
+            }
+        }
+
+        match block {
+            Some((kind, name, start, _)) => Err(format!("{}: {} '{}' (línea {}) sin 'end'", path, kind, name, start)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Material>> {
+        self.materials.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.materials.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_are_registered_under_their_own_name() {
+        let mut registry = MaterialRegistry::with_defaults();
+        assert!(registry.get("gas_giant2").is_some());
+        assert!(registry.get("nebula").is_none());
+
+        registry.register("gas_giant3", GasGiantMaterial { band_frequency: 14.0, ..GasGiantMaterial::violet() });
+        assert!(registry.names().contains(&"gas_giant3"));
+    }
+
+    #[test]
+    fn biome_tables_in_the_scene_file_become_planets() {
+        let mut registry = MaterialRegistry::new();
+        registry.load_scene("assets/scene.txt").unwrap();
+        assert!(registry.get("earth").is_some());
+    }
+}
@@ -0,0 +1,210 @@
+use nalgebra_glm::Vec3;
+use fastnoise_lite::{
+    CellularDistanceFunction, CellularReturnType, DomainWarpType, FastNoiseLite, FractalType, NoiseType,
+};
+
+// Deformación del dominio antes de muestrear: da formas retorcidas y fluidas
+#[derive(Debug, Clone, Copy)]
+pub struct DomainWarp {
+    pub warp_type: DomainWarpType,
+    pub amplitude: f32,
+    pub frequency: f32,
+    // Octavas de la deformación (1 = sin fractal)
+    pub octaves: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cellular {
+    pub distance: CellularDistanceFunction,
+    pub return_type: CellularReturnType,
+    pub jitter: f32,
+}
+
+// Octavas que suma `Noise::fractal` o el fractal propio de FastNoiseLite: frecuencia
+// de la primera, cuántas son y cuánto crece la frecuencia y baja la amplitud de una a la siguiente
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fractal {
+    pub scale: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Fractal {
+    pub const fn new(scale: f32, octaves: u32, lacunarity: f32, gain: f32) -> Self {
+        Fractal { scale, octaves, lacunarity, gain }
+    }
+}
+
+// Configuración del ruido de un cuerpo; se construye una vez con `build`
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseConfig {
+    pub seed: i32,
+    pub noise_type: NoiseType,
+    pub fractal_type: FractalType,
+    // Con `FractalType::None` solo cuenta la escala, que es la frecuencia del ruido
+    pub fractal: Fractal,
+    pub domain_warp: Option<DomainWarp>,
+    pub cellular: Option<Cellular>,
+}
+
+impl NoiseConfig {
+    // OpenSimplex2 sin fractal, con la frecuencia por defecto de FastNoiseLite
+    pub fn new(seed: i32) -> Self {
+        NoiseConfig {
+            seed,
+            noise_type: NoiseType::OpenSimplex2,
+            fractal_type: FractalType::None,
+            fractal: Fractal::new(0.01, 3, 2.0, 0.5),
+            domain_warp: None,
+            cellular: None,
+        }
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.fractal.scale = frequency;
+        self
+    }
+
+    pub fn with_fractal(mut self, fractal_type: FractalType, fractal: Fractal) -> Self {
+        self.fractal_type = fractal_type;
+        self.fractal = fractal;
+        self
+    }
+
+    pub fn with_domain_warp(mut self, domain_warp: DomainWarp) -> Self {
+        self.domain_warp = Some(domain_warp);
+        self
+    }
+
+    // Ruido celular (Voronoi) con sus propias opciones
+    pub fn cellular(mut self, cellular: Cellular) -> Self {
+        self.noise_type = NoiseType::Cellular;
+        self.cellular = Some(cellular);
+        self
+    }
+
+    pub fn build(&self) -> Noise {
+        let mut noise = FastNoiseLite::with_seed(self.seed);
+        noise.set_noise_type(Some(self.noise_type));
+        noise.set_frequency(Some(self.fractal.scale));
+        noise.set_fractal_type(Some(self.fractal_type));
+        noise.set_fractal_octaves(Some(self.fractal.octaves as i32));
+        noise.set_fractal_lacunarity(Some(self.fractal.lacunarity));
+        noise.set_fractal_gain(Some(self.fractal.gain));
+        if let Some(cellular) = &self.cellular {
+            noise.set_cellular_distance_function(Some(cellular.distance));
+            noise.set_cellular_return_type(Some(cellular.return_type));
+            noise.set_cellular_jitter(Some(cellular.jitter));
+        }
+
+        // La deformación usa su propio generador con otra semilla
+        let warp = self.domain_warp.map(|domain_warp| {
+            let mut warp = FastNoiseLite::with_seed(self.seed.wrapping_add(1));
+            warp.set_domain_warp_type(Some(domain_warp.warp_type));
+            warp.set_domain_warp_amp(Some(domain_warp.amplitude));
+            warp.set_frequency(Some(domain_warp.frequency));
+            if domain_warp.octaves > 1 {
+                warp.set_fractal_type(Some(FractalType::DomainWarpProgressive));
+                warp.set_fractal_octaves(Some(domain_warp.octaves as i32));
+            }
+            warp
+        });
+
+        Noise { noise, warp }
+    }
+}
+
+// La configuración que usaban todos los cuerpos antes: semilla 1337 y OpenSimplex2
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig::new(1337)
+    }
+}
+
+// Generador listo para muestrear, con la deformación de dominio ya aplicada
+pub struct Noise {
+    noise: FastNoiseLite,
+    warp: Option<FastNoiseLite>,
+}
+
+impl Noise {
+    pub fn get_noise_2d(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = match &self.warp {
+            Some(warp) => warp.domain_warp_2d(x, y),
+            None => (x, y),
+        };
+        self.noise.get_noise_2d(x, y)
+    }
+
+    pub fn get_noise_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (x, y, z) = match &self.warp {
+            Some(warp) => warp.domain_warp_3d(x, y, z),
+            None => (x, y, z),
+        };
+        self.noise.get_noise_3d(x, y, z)
+    }
+
+    // Suma de octavas a mano sobre el ruido del cuerpo, normalizada por las amplitudes;
+    // `shape` transforma cada octava (identidad para fbm, crestas para ridged)
+    pub fn fractal(&self, position: &Vec3, fractal: Fractal, shape: impl Fn(f32) -> f32) -> f32 {
+        let mut frequency = fractal.scale;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for _ in 0..fractal.octaves.max(1) {
+            let p = position * frequency;
+            sum += shape(self.get_noise_3d(p.x, p.y, p.z)) * amplitude;
+            total += amplitude;
+            frequency *= fractal.lacunarity;
+            amplitude *= fractal.gain;
+        }
+        sum / total
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        NoiseConfig::default().build()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_matches_plain_open_simplex() {
+        let mut plain = FastNoiseLite::with_seed(1337);
+        plain.set_noise_type(Some(NoiseType::OpenSimplex2));
+        let noise = Noise::default();
+
+        for (x, y, z) in [(0.0, 0.0, 0.0), (12.5, -3.0, 80.0), (400.0, 250.0, -90.0)] {
+            assert_eq!(noise.get_noise_3d(x, y, z), plain.get_noise_3d(x, y, z));
+        }
+    }
+
+    #[test]
+    fn seeds_give_different_surfaces() {
+        let a = NoiseConfig::new(1).build();
+        let b = NoiseConfig::new(2).build();
+        let differs = (0..16).any(|i| {
+            let p = i as f32 * 37.0;
+            a.get_noise_3d(p, p * 0.5, -p) != b.get_noise_3d(p, p * 0.5, -p)
+        });
+        assert!(differs);
+    }
+
+    #[test]
+    fn domain_warp_moves_the_pattern() {
+        let warp = DomainWarp { warp_type: DomainWarpType::OpenSimplex2, amplitude: 40.0, frequency: 0.01, octaves: 2 };
+        let plain = NoiseConfig::new(5).build();
+        let warped = NoiseConfig::new(5).with_domain_warp(warp).build();
+        let differs = (0..16).any(|i| {
+            let p = i as f32 * 23.0;
+            plain.get_noise_2d(p, -p) != warped.get_noise_2d(p, -p)
+        });
+        assert!(differs);
+    }
+}
@@ -1,4 +1,3 @@
-use tobj;
 use nalgebra_glm::{Vec2, Vec3};
 use crate::vertex::Vertex;
 
@@ -44,19 +43,85 @@ impl Obj {
         let mut vertices = Vec::new();
 
         for mesh in &self.meshes {
+            let (tangents, bitangents) = mesh.tangents();
             for &index in &mesh.indices {
-                let position = mesh.vertices[index as usize];
-                let normal = mesh.normals.get(index as usize)
+                let index = index as usize;
+                let position = mesh.vertices[index];
+                let normal = mesh.normals.get(index)
                     .cloned()
                     .unwrap_or(Vec3::new(0.0, 1.0, 0.0));
-                let tex_coords = mesh.texcoords.get(index as usize)
+                let tex_coords = mesh.texcoords.get(index)
                     .cloned()
                     .unwrap_or(Vec2::new(0.0, 0.0));
 
-                vertices.push(Vertex::new(position, normal, tex_coords));
+                vertices.push(Vertex::new(position, normal, tex_coords).with_tangents(tangents[index], bitangents[index]));
             }
         }
 
         vertices
     }
 }
+
+impl Mesh {
+    // Tangente y bitangente por vértice: las direcciones del modelo en que crecen u y v,
+    // sumadas sobre los triángulos que lo comparten. Donde no hay coordenadas de textura
+    // quedan en cero y el vértice usa una base cualquiera alrededor de su normal
+    fn tangents(&self) -> (Vec<Vec3>, Vec<Vec3>) {
+        let mut tangents = vec![Vec3::zeros(); self.vertices.len()];
+        let mut bitangents = vec![Vec3::zeros(); self.vertices.len()];
+        if self.texcoords.len() < self.vertices.len() {
+            return (tangents, bitangents);
+        }
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (edge1, edge2) = (self.vertices[b] - self.vertices[a], self.vertices[c] - self.vertices[a]);
+            let (duv1, duv2) = (self.texcoords[b] - self.texcoords[a], self.texcoords[c] - self.texcoords[a]);
+
+            let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+
+        (tangents, bitangents)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tangents_follow_the_texture_coordinates() {
+        // Cuadrado en el plano xz con u hacia +x y v hacia -z
+        let mesh = Mesh {
+            vertices: vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0)],
+            normals: vec![Vec3::y(); 4],
+            texcoords: vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0)],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        };
+        let vertices = Obj { meshes: vec![mesh] }.get_vertex_array();
+        for vertex in &vertices {
+            assert!((vertex.tangent - Vec3::x()).magnitude() < 1e-5, "{:?}", vertex.tangent);
+            assert!((vertex.bitangent + Vec3::z()).magnitude() < 1e-5, "{:?}", vertex.bitangent);
+        }
+    }
+
+    #[test]
+    fn loaded_tangent_frames_are_orthonormal() {
+        let vertices = Obj::load("assets/models/sphere2.obj").expect("modelo de la esfera").get_vertex_array();
+        for vertex in &vertices {
+            assert!(vertex.tangent.dot(&vertex.normal).abs() < 1e-3);
+            assert!(vertex.bitangent.dot(&vertex.tangent).abs() < 1e-3);
+            assert!((vertex.tangent.magnitude() - 1.0).abs() < 1e-3);
+        }
+    }
+}
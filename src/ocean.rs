@@ -0,0 +1,171 @@
+use nalgebra_glm::{Vec3, reflect_vec};
+use crate::color::Color;
+use crate::fragment::Fragment;
+use crate::light::{blinn_phong, LightKind, Specular};
+use crate::utils::smoothstep;
+use crate::Uniforms;
+
+// Una familia de olas: ruido estirado para que las crestas queden perpendiculares a
+// la dirección y desplazado a lo largo de ella con el tiempo
+#[derive(Debug, Clone, Copy)]
+pub struct WaveLayer {
+    // Hacia dónde viajan, en espacio de objeto
+    pub direction: Vec3,
+    // Escala del ruido (como en el resto de los shaders) y altura en unidades del modelo
+    pub scale: f32,
+    pub amplitude: f32,
+    // Unidades de ruido por segundo
+    pub speed: f32,
+    // Cuánto más rápido varía el ruido a lo largo de la dirección que a lo ancho
+    pub crest: f32,
+}
+
+// Iluminación del agua abierta: olas animadas en la normal, reflejo del cielo
+// pesado por Fresnel y el destello del sol, que se mueve con quien mira
+pub struct Ocean {
+    pub waves: Vec<WaveLayer>,
+    // Color del agua poco profunda y profundidad (bajo el nivel del mar) a la que
+    // ya se ve el color del fondo del material
+    pub shallow_color: Color,
+    pub shallow_depth: f32,
+    // Color lineal del cielo que se refleja de día
+    pub sky_color: Vec3,
+    // Reflectancia con incidencia normal (agua ≈ 0.02)
+    pub fresnel: f32,
+    // Destello nítido del sol y brillo ancho alrededor
+    pub glint_power: f32,
+    pub glint_strength: f32,
+    pub sheen_power: f32,
+    pub sheen_strength: f32,
+}
+
+impl Default for Ocean {
+    fn default() -> Self {
+        Ocean {
+            waves: vec![
+                WaveLayer { direction: Vec3::new(1.0, 0.0, 0.3).normalize(), scale: 900.0, amplitude: 0.0012, speed: 1.2, crest: 2.0 },
+                WaveLayer { direction: Vec3::new(-0.4, 0.2, 1.0).normalize(), scale: 1700.0, amplitude: 0.0006, speed: 1.8, crest: 1.5 },
+                WaveLayer { direction: Vec3::new(0.7, -0.3, -0.6).normalize(), scale: 3200.0, amplitude: 0.0003, speed: 2.6, crest: 1.0 },
+            ],
+            shallow_color: Color::new(56, 170, 180),
+            shallow_depth: 0.06,
+            sky_color: Vec3::new(0.3, 0.5, 0.85),
+            fresnel: 0.02,
+            glint_power: 900.0,
+            glint_strength: 6.0,
+            sheen_power: 60.0,
+            sheen_strength: 0.25,
+        }
+    }
+}
+
+impl Ocean {
+    // Altura de las olas en un punto del modelo y un instante
+    pub fn height(&self, uniforms: &Uniforms, position: &Vec3) -> f32 {
+        self.waves.iter()
+            .map(|wave| {
+                let p = position * wave.scale;
+                let along = p.dot(&wave.direction);
+                let q = p + wave.direction * (along * wave.crest - wave.speed * uniforms.time);
+                uniforms.noise.get_noise_3d(q.x, q.y, q.z) * wave.amplitude
+            })
+            .sum()
+    }
+
+    // Normal en espacio de mundo con las olas encima de la normal geométrica
+    pub fn wave_normal(&self, fragment: &Fragment, uniforms: &Uniforms) -> Vec3 {
+        fragment.bump_normal(1.0, |point| self.height(uniforms, point))
+    }
+
+    // Cerca de la costa el fondo se acerca y el agua se aclara
+    pub fn shallows(&self, albedo: Color, depth: f32) -> Color {
+        let t = smoothstep(0.0, self.shallow_depth, depth);
+        self.shallow_color.lerp(&albedo, t).with_alpha(albedo.alpha())
+    }
+
+    // `depth` es la profundidad bajo el nivel del mar (infinita en mar abierto) y
+    // `specular_mask` atenúa el destello donde el material lo pide
+    pub fn light(&self, albedo: Color, depth: f32, specular_mask: f32, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let normal = self.wave_normal(fragment, uniforms);
+        let albedo = self.shallows(albedo, depth);
+        let body = blinn_phong(fragment, &normal, uniforms.lights, &Specular::matte()).apply(albedo, 0.0);
+
+        // Schlick: el agua refleja poco de frente y casi todo en el borde
+        let cosine = normal.dot(&fragment.view_dir).clamp(0.0, 1.0);
+        let fresnel = self.fresnel + (1.0 - self.fresnel) * (1.0 - cosine).powi(5);
+
+        // El cielo solo se ve reflejado del lado de día
+        let day = smoothstep(-0.1, 0.2, fragment.normal.dot(&fragment.light_dir)) * fragment.sunlight();
+        let sky = self.sky_color * day;
+
+        // El sol reflejado: la dirección de la vista espejada contra la ola
+        let reflected = reflect_vec(&-fragment.view_dir, &normal);
+        let alignment = reflected.dot(&fragment.light_dir).max(0.0);
+        let highlight = alignment.powf(self.glint_power) * self.glint_strength + alignment.powf(self.sheen_power) * self.sheen_strength;
+        let glint = sun_radiance(uniforms) * (highlight * fragment.sunlight() * specular_mask.clamp(0.0, 1.0));
+
+        let color = body.to_vec3() * (1.0 - fresnel) + sky * fresnel + glint;
+        Color::from_vec3(color).with_alpha(albedo.alpha())
+    }
+}
+
+// Color e intensidad de la luz principal (la primera que no es ambiental)
+fn sun_radiance(uniforms: &Uniforms) -> Vec3 {
+    uniforms.lights.iter()
+        .find(|light| !matches!(light.kind, LightKind::Ambient))
+        .map_or(Vec3::zeros(), |light| light.color * light.intensity)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::noise::NoiseConfig;
+
+    fn surface_point() -> Fragment {
+        let point = Vec3::new(0.0, 0.0, 1.0);
+        let mut fragment = Fragment::new(0.0, 0.0, 0.0, Vec3::z(), 1.0, point, point);
+        fragment.light_dir = Vec3::new(1.0, 0.0, 1.0).normalize();
+        fragment
+    }
+
+    #[test]
+    fn the_sun_glint_moves_with_the_viewer() {
+        let noise = NoiseConfig::new(53).build();
+        let lights = [Light::directional(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let uniforms = Uniforms { lights: &lights, ..Uniforms::object_space(&noise) };
+        // Mar en calma para que solo cuente la geometría
+        let ocean = Ocean { waves: Vec::new(), ..Ocean::default() };
+        let brightness = |view: Vec3| {
+            let mut fragment = surface_point();
+            fragment.view_dir = view.normalize();
+            ocean.light(Color::new(10, 50, 120), f32::INFINITY, 1.0, &fragment, &uniforms).to_vec3().x
+        };
+
+        // Mirando desde el reflejo del sol se ve el destello; desde otro lado no
+        let mirrored = brightness(Vec3::new(-1.0, 0.0, 1.0));
+        let elsewhere = brightness(Vec3::new(0.0, 0.3, 1.0));
+        assert!(mirrored > elsewhere * 5.0, "{} {}", mirrored, elsewhere);
+    }
+
+    #[test]
+    fn waves_roll_with_time() {
+        let noise = NoiseConfig::new(53).build();
+        let ocean = Ocean::default();
+        let fragment = surface_point();
+        let normal_at = |time: f32| ocean.wave_normal(&fragment, &Uniforms { time, ..Uniforms::object_space(&noise) });
+
+        assert!((normal_at(0.0) - Vec3::z()).magnitude() > 1e-3);
+        assert!((normal_at(0.0) - normal_at(1.5)).magnitude() > 1e-3);
+    }
+
+    #[test]
+    fn water_brightens_toward_the_coast() {
+        let ocean = Ocean::default();
+        let deep = Color::new(10, 50, 120);
+        assert!((ocean.shallows(deep, 0.0).to_vec3() - ocean.shallow_color.to_vec3()).magnitude() < 1e-5);
+        assert!((ocean.shallows(deep, ocean.shallow_depth).to_vec3() - deep.to_vec3()).magnitude() < 1e-5);
+        assert!((ocean.shallows(deep, f32::INFINITY).to_vec3() - deep.to_vec3()).magnitude() < 1e-5);
+    }
+}
@@ -0,0 +1,353 @@
+use nalgebra_glm::Vec3;
+use crate::framebuffer::Framebuffer;
+use crate::tonemap::ToneMapping;
+use crate::utils::smoothstep;
+
+// Efectos de postproceso. Los que trabajan en HDR lineal se aplican antes del
+// mapeo de tonos; el grano y la gradación de color sobre la imagen final.
+pub enum Effect {
+    // Resplandor a partir de los pixeles con luminancia sobre el umbral
+    Bloom { threshold: f32, intensity: f32, radius: usize },
+    // Desplazamiento radial de los canales rojo y azul, en pixeles en la esquina
+    ChromaticAberration { strength: f32 },
+    // Oscurecimiento hacia los bordes a partir de `radius` (0 centro, 1 esquina)
+    Vignette { strength: f32, radius: f32 },
+    FilmGrain { amount: f32 },
+    ColorGrading { lut: Lut, strength: f32 },
+}
+
+impl Effect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Bloom { .. } => "bloom",
+            Effect::ChromaticAberration { .. } => "aberration",
+            Effect::Vignette { .. } => "vignette",
+            Effect::FilmGrain { .. } => "grain",
+            Effect::ColorGrading { .. } => "lut",
+        }
+    }
+
+    fn is_hdr(&self) -> bool {
+        !matches!(self, Effect::FilmGrain { .. } | Effect::ColorGrading { .. })
+    }
+
+    fn apply(&self, framebuffer: &mut Framebuffer, time: f32) {
+        match self {
+            Effect::Bloom { threshold, intensity, radius } => bloom(framebuffer, *threshold, *intensity, *radius),
+            Effect::ChromaticAberration { strength } => chromatic_aberration(framebuffer, *strength),
+            Effect::Vignette { strength, radius } => vignette(framebuffer, *strength, *radius),
+            Effect::FilmGrain { amount } => film_grain(framebuffer, *amount, time),
+            Effect::ColorGrading { lut, strength } => color_grading(framebuffer, lut, *strength),
+        }
+    }
+}
+
+pub struct Pass {
+    pub effect: Effect,
+    pub enabled: bool,
+}
+
+// Cadena de pasadas en el orden en que se agregaron
+pub struct PostProcessing {
+    pub passes: Vec<Pass>,
+}
+
+impl PostProcessing {
+    pub fn new() -> Self {
+        PostProcessing { passes: Vec::new() }
+    }
+
+    pub fn with(mut self, effect: Effect) -> Self {
+        self.passes.push(Pass { effect, enabled: true });
+        self
+    }
+
+    // Devuelve el nuevo estado, o None si no hay una pasada con ese nombre
+    pub fn toggle(&mut self, name: &str) -> Option<bool> {
+        let pass = self.passes.iter_mut().find(|pass| pass.effect.name() == name)?;
+        pass.enabled = !pass.enabled;
+        Some(pass.enabled)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for pass in self.passes.iter_mut().filter(|pass| pass.effect.name() == name) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn enabled_names(&self) -> Vec<&'static str> {
+        self.passes.iter()
+            .filter(|pass| pass.enabled)
+            .map(|pass| pass.effect.name())
+            .collect()
+    }
+
+    // Pasadas HDR, mapeo de tonos a `buffer` y luego las pasadas de pantalla
+    pub fn apply(&self, framebuffer: &mut Framebuffer, tone_mapping: &ToneMapping, time: f32) {
+        let enabled = || self.passes.iter().filter(|pass| pass.enabled);
+
+        for pass in enabled().filter(|pass| pass.effect.is_hdr()) {
+            pass.effect.apply(framebuffer, time);
+        }
+
+        framebuffer.tone_map(tone_mapping);
+
+        for pass in enabled().filter(|pass| !pass.effect.is_hdr()) {
+            pass.effect.apply(framebuffer, time);
+        }
+    }
+}
+
+impl Default for PostProcessing {
+    fn default() -> Self {
+        PostProcessing::new()
+    }
+}
+
+// Tabla 3D de gradación en sRGB, guardada con el rojo variando más rápido
+pub struct Lut {
+    size: usize,
+    data: Vec<Vec3>,
+}
+
+impl Lut {
+    #[cfg(test)]
+    pub fn identity(size: usize) -> Self {
+        let scale = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push(Vec3::new(r as f32, g as f32, b as f32) * scale);
+                }
+            }
+        }
+        Lut { size, data }
+    }
+
+    // Imagen en tira horizontal: `size` cuadros de size x size, uno por nivel de azul
+    pub fn load(path: &str) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| format!("{}: {}", path, e))?.to_rgb8();
+        let size = image.height() as usize;
+        if size < 2 || image.width() as usize != size * size {
+            return Err(format!("{}: se esperaba una tira de {}x{}", path, size * size, size));
+        }
+
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let pixel = image.get_pixel((b * size + r) as u32, g as u32);
+                    data.push(Vec3::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32) / 255.0);
+                }
+            }
+        }
+        Ok(Lut { size, data })
+    }
+
+    fn at(&self, r: usize, g: usize, b: usize) -> Vec3 {
+        self.data[(b * self.size + g) * self.size + r]
+    }
+
+    // Interpolación trilineal de un color sRGB en [0, 1]
+    pub fn sample(&self, color: Vec3) -> Vec3 {
+        let max = (self.size - 1) as f32;
+        let coord = color.map(|c| c.clamp(0.0, 1.0) * max);
+        let base = coord.map(|c| c.floor().min(max - 1.0));
+        let t = coord - base;
+        let (r, g, b) = (base.x as usize, base.y as usize, base.z as usize);
+
+        let lerp = |a: Vec3, b: Vec3, t: f32| a + (b - a) * t;
+        let c00 = lerp(self.at(r, g, b), self.at(r + 1, g, b), t.x);
+        let c10 = lerp(self.at(r, g + 1, b), self.at(r + 1, g + 1, b), t.x);
+        let c01 = lerp(self.at(r, g, b + 1), self.at(r + 1, g, b + 1), t.x);
+        let c11 = lerp(self.at(r, g + 1, b + 1), self.at(r + 1, g + 1, b + 1), t.x);
+        lerp(lerp(c00, c10, t.y), lerp(c01, c11, t.y), t.z)
+    }
+}
+
+fn luminance(color: &Vec3) -> f32 {
+    color.dot(&Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Lectura bilineal con los bordes repetidos
+fn sample_bilinear(buffer: &[Vec3], width: usize, height: usize, x: f32, y: f32) -> Vec3 {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let top = buffer[y0 * width + x0] * (1.0 - tx) + buffer[y0 * width + x1] * tx;
+    let bottom = buffer[y1 * width + x0] * (1.0 - tx) + buffer[y1 * width + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+fn gaussian_kernel(radius: usize) -> Vec<f32> {
+    let sigma = (radius as f32 * 0.5).max(0.5);
+    let weights: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-(d * d) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+// Desenfoque separable en una dirección
+fn blur(source: &[Vec3], width: usize, height: usize, kernel: &[f32], horizontal: bool) -> Vec<Vec3> {
+    let radius = (kernel.len() / 2) as isize;
+    let mut result = vec![Vec3::zeros(); source.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = Vec3::zeros();
+            for (i, weight) in kernel.iter().enumerate() {
+                let offset = i as isize - radius;
+                let (sx, sy) = if horizontal {
+                    ((x as isize + offset).clamp(0, width as isize - 1) as usize, y)
+                } else {
+                    (x, (y as isize + offset).clamp(0, height as isize - 1) as usize)
+                };
+                sum += source[sy * width + sx] * *weight;
+            }
+            result[y * width + x] = sum;
+        }
+    }
+    result
+}
+
+// Extrae lo brillante a media resolución, lo desenfoca y lo suma a la imagen
+fn bloom(framebuffer: &mut Framebuffer, threshold: f32, intensity: f32, radius: usize) {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut bright = vec![Vec3::zeros(); half_width * half_height];
+
+    for y in 0..half_height {
+        for x in 0..half_width {
+            let mut color = Vec3::zeros();
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = (x * 2 + dx).min(width - 1);
+                let sy = (y * 2 + dy).min(height - 1);
+                color += framebuffer.color_buffer[sy * width + sx] * 0.25;
+            }
+
+            // Solo la parte de la luminancia que pasa el umbral
+            let luma = luminance(&color);
+            if luma > threshold {
+                bright[y * half_width + x] = color * ((luma - threshold) / luma);
+            }
+        }
+    }
+
+    let kernel = gaussian_kernel(radius);
+    let bright = blur(&bright, half_width, half_height, &kernel, true);
+    let bright = blur(&bright, half_width, half_height, &kernel, false);
+
+    for y in 0..height {
+        for x in 0..width {
+            let glow = sample_bilinear(&bright, half_width, half_height, x as f32 * 0.5 - 0.25, y as f32 * 0.5 - 0.25);
+            framebuffer.color_buffer[y * width + x] += glow * intensity;
+        }
+    }
+}
+
+// Rojo hacia afuera y azul hacia adentro, más separados cerca de los bordes
+fn chromatic_aberration(framebuffer: &mut Framebuffer, strength: f32) {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    let source = framebuffer.color_buffer.clone();
+    let center = Vec3::new(width as f32 * 0.5, height as f32 * 0.5, 0.0);
+    let half_diagonal = center.magnitude();
+
+    for y in 0..height {
+        for x in 0..width {
+            let offset = (Vec3::new(x as f32, y as f32, 0.0) - center) / half_diagonal;
+            let shift = offset * offset.magnitude() * strength;
+
+            let red = sample_bilinear(&source, width, height, x as f32 - shift.x, y as f32 - shift.y).x;
+            let blue = sample_bilinear(&source, width, height, x as f32 + shift.x, y as f32 + shift.y).z;
+            let pixel = &mut framebuffer.color_buffer[y * width + x];
+            pixel.x = red;
+            pixel.z = blue;
+        }
+    }
+}
+
+fn vignette(framebuffer: &mut Framebuffer, strength: f32, radius: f32) {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    let (center_x, center_y) = (width as f32 * 0.5, height as f32 * 0.5);
+
+    for y in 0..height {
+        for x in 0..width {
+            let dx = (x as f32 - center_x) / center_x;
+            let dy = (y as f32 - center_y) / center_y;
+            let distance = (dx * dx + dy * dy).sqrt() / std::f32::consts::SQRT_2;
+            framebuffer.color_buffer[y * width + x] *= 1.0 - strength * smoothstep(radius, 1.0, distance);
+        }
+    }
+}
+
+// Ruido blanco por pixel; cambia 24 veces por segundo como en el cine
+fn film_grain(framebuffer: &mut Framebuffer, amount: f32, time: f32) {
+    let frame = (time * 24.0) as u32;
+
+    for (index, pixel) in framebuffer.buffer.iter_mut().enumerate() {
+        let mut hash = (index as u32).wrapping_mul(0x9E37_79B9) ^ frame.wrapping_mul(0x85EB_CA6B);
+        hash ^= hash >> 16;
+        hash = hash.wrapping_mul(0x7FEB_352D);
+        hash ^= hash >> 15;
+        let noise = (hash & 0xFFFF) as f32 / 65535.0 * 2.0 - 1.0;
+        let grain = (noise * amount * 255.0) as i32;
+
+        let channel = |shift: u32| ((((*pixel >> shift) & 0xFF) as i32 + grain).clamp(0, 255) as u32) << shift;
+        *pixel = channel(16) | channel(8) | channel(0);
+    }
+}
+
+fn color_grading(framebuffer: &mut Framebuffer, lut: &Lut, strength: f32) {
+    for pixel in framebuffer.buffer.iter_mut() {
+        let color = Vec3::new(
+            ((*pixel >> 16) & 0xFF) as f32,
+            ((*pixel >> 8) & 0xFF) as f32,
+            (*pixel & 0xFF) as f32,
+        ) / 255.0;
+        let graded = color + (lut.sample(color) - color) * strength;
+
+        let encode = |c: f32| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u32;
+        *pixel = (encode(graded.x) << 16) | (encode(graded.y) << 8) | encode(graded.z);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_lut_keeps_colors() {
+        let lut = Lut::identity(16);
+        for color in [Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 0.61, 0.95), Vec3::new(1.0, 1.0, 1.0)] {
+            assert!((lut.sample(color) - color).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn vignette_leaves_center_untouched() {
+        let mut framebuffer = Framebuffer::new(9, 9);
+        framebuffer.color_buffer.fill(Vec3::new(1.0, 1.0, 1.0));
+        vignette(&mut framebuffer, 0.8, 0.3);
+
+        assert_eq!(framebuffer.color_buffer[4 * 9 + 4], Vec3::new(1.0, 1.0, 1.0));
+        assert!(framebuffer.color_buffer[0].x < 0.5);
+    }
+
+    #[test]
+    fn bloom_ignores_pixels_below_threshold() {
+        let mut framebuffer = Framebuffer::new(16, 16);
+        framebuffer.color_buffer.fill(Vec3::new(0.5, 0.5, 0.5));
+        bloom(&mut framebuffer, 1.0, 1.0, 3);
+
+        assert!(framebuffer.color_buffer.iter().all(|c| *c == Vec3::new(0.5, 0.5, 0.5)));
+    }
+}
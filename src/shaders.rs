@@ -1,9 +1,26 @@
 
+use std::f32::consts::PI;
+use std::sync::Arc;
 use nalgebra_glm::{Vec3, Vec4, Mat3, mat4_to_mat3};
 use crate::vertex::Vertex;
+use crate::utils::smoothstep;
 use crate::Uniforms;
 use crate::fragment::Fragment;
 use crate::color::Color;
+use crate::light::{blinn_phong, night_side, Specular};
+use crate::cities::CityLights;
+use crate::craters::Craters;
+use crate::ocean::Ocean;
+use crate::material::{Material, Surface};
+use crate::noise::Fractal;
+use crate::texture::NormalMap;
+
+// Brillo especular de cada tipo de superficie
+const ROCK_SPECULAR: Specular = Specular::new(12.0, Vec3::new(1.0, 0.95, 0.85), 0.08);
+const SAND_SPECULAR: Specular = Specular::new(8.0, Vec3::new(1.0, 0.95, 0.85), 0.05);
+const ICE_SPECULAR: Specular = Specular::new(48.0, Vec3::new(0.9, 0.95, 1.0), 0.6);
+pub const WATER_SPECULAR: Specular = Specular::new(90.0, Vec3::new(1.0, 0.97, 0.9), 0.9);
+const HULL_SPECULAR: Specular = Specular::new(64.0, Vec3::new(0.95, 0.97, 1.0), 0.8);
 
 
 pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
@@ -14,7 +31,8 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
         1.0
     );
 
-    let transformed = uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix * position;
+    let world_position = uniforms.model_matrix * position;
+    let transformed = uniforms.projection_matrix * uniforms.view_matrix * world_position;
 
     let w = transformed.w;
     let transformed_position = Vec4::new(
@@ -29,584 +47,1026 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
     let model_mat3 = mat4_to_mat3(&uniforms.model_matrix);
     let normal_matrix = model_mat3.transpose().try_inverse().unwrap_or(Mat3::identity());
 
-    let transformed_normal = normal_matrix * vertex.normal;
+    let transformed_normal = (normal_matrix * vertex.normal).normalize();
+    // Las tangentes siguen a la superficie, así que van con la matriz del modelo
+    let transformed_tangent = (model_mat3 * vertex.tangent).normalize();
+    let transformed_bitangent = (model_mat3 * vertex.bitangent).normalize();
 
     Vertex {
         position: vertex.position,
         normal: vertex.normal,
+        tangent: vertex.tangent,
+        bitangent: vertex.bitangent,
         tex_coords: vertex.tex_coords,
         color: vertex.color,
         transformed_position: Vec3::new(screen_position.x, screen_position.y, screen_position.z),
-        transformed_normal: transformed_normal
+        transformed_normal,
+        transformed_tangent,
+        transformed_bitangent,
+        world_position: world_position.xyz(),
+        inverse_w: 1.0 / w,
     }
 }
 
-pub fn ring_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let color_palette = [
+// Muestra el ruido del cuerpo en la posición escalada
+fn sample(uniforms: &Uniforms, position: &Vec3, scale: f32) -> f32 {
+    uniforms.noise.get_noise_3d(position.x * scale, position.y * scale, position.z * scale)
+}
+
+// Paleta de bandas cálidas del gigante gaseoso y su aro, en sRGB
+fn warm_bands() -> Vec<Vec3> {
+    vec![
         Vec3::new(235.0 / 255.0, 91.0 / 255.0, 96.0 / 255.0),
         Vec3::new(237.0 / 255.0, 112.0 / 255.0, 47.0 / 255.0),
         Vec3::new(234.0 / 255.0, 116.0 / 255.0, 92.0 / 255.0),
         Vec3::new(235.0 / 255.0, 91.0 / 255.0, 181.0 / 255.0),
         Vec3::new(235.0 / 255.0, 165.0 / 255.0, 91.0 / 255.0),
-    ];
+    ]
+}
 
-    let noise_scale = 1.0;
-    let noise_variation = uniforms.noise.get_noise_2d(
-        fragment.vertex_position.x * noise_scale,
-        fragment.vertex_position.y * noise_scale,
-    );
+// Elige la banda de la paleta para un valor de franja en [-1, 1]
+fn band_color(palette: &[Vec3], stripe: f32, noise_variation: f32) -> Vec3 {
+    let index = ((stripe + 1.0) / 2.0 * (palette.len() as f32)) as usize % palette.len();
+    palette[index] * (1.0 + noise_variation * 0.1)
+}
 
-    // Calcular el ángulo y la distancia para crear franjas alrededor del aro
-    let angle = fragment.vertex_position.x.atan2(fragment.vertex_position.y);
-    let radius = fragment.vertex_position.len();
 
-    // Generar patrón de bandas
-    let stripe_pattern = (angle * 8.0 + noise_variation * 1.5).sin();
-    let color_index = ((stripe_pattern + 1.0) / 2.0 * (color_palette.len() as f32)) as usize % color_palette.len();
-    let mut gas_color = color_palette[color_index] * (1.0 + noise_variation * 0.1);
+// Una división del aro: posición y ancho en [0, 1] y la opacidad que deja pasar
+#[derive(Debug, Clone, Copy)]
+pub struct RingGap {
+    pub center: f32,
+    pub width: f32,
+    pub min_opacity: f32,
+}
 
-    // Ajuste de intensidad para efectos de iluminación
-    gas_color = gas_color * fragment.intensity;
+pub struct RingMaterial {
+    // Colores en sRGB; se decodifican después de variar el tono
+    pub palette: Vec<Vec3>,
+    pub band_count: f32,
+    // Radios del modelo en el plano del aro
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    pub opacity: f32,
+    pub ringlet_scale: f32,
+    pub ringlet_strength: f32,
+    pub gaps: Vec<RingGap>,
+}
 
-    Color::new(
-        (gas_color.x * 255.0) as u8,
-        (gas_color.y * 255.0) as u8,
-        (gas_color.z * 255.0) as u8,
-    )
+impl Default for RingMaterial {
+    fn default() -> Self {
+        RingMaterial {
+            palette: warm_bands(),
+            band_count: 8.0,
+            inner_radius: 0.73,
+            outer_radius: 1.25,
+            opacity: 0.85,
+            ringlet_scale: 1500.0,
+            ringlet_strength: 0.15,
+            // Una ancha a media distancia y una delgada cerca del borde exterior
+            gaps: vec![
+                RingGap { center: 0.58, width: 0.06, min_opacity: 0.08 },
+                RingGap { center: 0.86, width: 0.02, min_opacity: 0.2 },
+            ],
+        }
+    }
 }
 
+impl Material for RingMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
+        let noise_variation = uniforms.noise.get_noise_2d(position.x, position.y);
 
+        // Franjas alrededor del aro según el ángulo
+        let angle = position.x.atan2(position.y);
+        let stripe_pattern = (angle * self.band_count + noise_variation * 1.5).sin();
+        let gas_color = band_color(&self.palette, stripe_pattern, noise_variation);
 
-pub fn rocky_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    // Capas de la superficiergb()
-    let mountain_color = Color::new(228, 179, 85);
-    let valley_color = Color::new(195, 126, 50);
-    let deep_color = Color::new(92, 51, 22);
+        // Opacidad según la distancia al centro en el plano del aro
+        let radius = (position.x.powi(2) + position.z.powi(2)).sqrt();
+        let t = ((radius - self.inner_radius) / (self.outer_radius - self.inner_radius)).clamp(0.0, 1.0);
 
-    // Configuración de posición y escala
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let base_scale = 800.0;
+        // Anillos finos con ruido a lo largo del radio
+        let ringlets = uniforms.noise.get_noise_2d(radius * self.ringlet_scale, 0.0) * self.ringlet_strength;
+        let mut opacity = self.opacity + ringlets;
 
-    // Calcular ruido de terreno para simular elevación
-    let base_noise = uniforms.noise.get_noise_3d(
-        position.x * base_scale, 
-        position.y * base_scale, 
-        position.z * base_scale,
-    );
+        for gap in &self.gaps {
+            opacity *= ((t - gap.center).abs() / gap.width).clamp(0.0, 1.0).max(gap.min_opacity);
+        }
 
-    // Generar variaciones de elevación en el terreno
-    let terrain_variation = uniforms.noise.get_noise_3d(
-        (position.x + 1000.0) * base_scale * 0.8, 
-        (position.y + 1000.0) * base_scale * 0.8, 
-        (position.z + 1000.0) * base_scale * 0.8,
-    ) * 0.6;
+        // Bordes difusos
+        opacity *= (t * 8.0).min(1.0) * ((1.0 - t) * 8.0).min(1.0);
 
-    let combined_terrain_value = (base_noise * 0.7 + terrain_variation * 0.3).clamp(0.0, 1.0);
+        Surface::new(Color::from_srgb_vec3(gas_color).with_alpha(opacity))
+    }
 
-    // Cráteres más dispersos y profundos
-    let crater_distribution = 0.3; // Frecuencia baja para mayor dispersión
-    let crater_depth = 3.0;
-    let crater_effect = ((position.x * crater_distribution).sin() * (position.y * crater_distribution).cos()).abs() * crater_depth;
-    
-    // Integración de valores de ruido y cráter para la textura de superficie
-    let mut surface_value = (combined_terrain_value - crater_effect).clamp(0.0, 1.0);
+    // La iluminación se aplica en lineal, después de decodificar la paleta
+    fn light(&self, surface: &Surface, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+        surface.albedo * fragment.intensity
+    }
 
-    // Añadir ruido fino para detalles en la superficie
-    let fine_texture = uniforms.noise.get_noise_3d(
-        position.x * 2000.0,
-        position.y * 2000.0,
-        position.z * 2000.0,
-    ) * 0.35;
+    // El patrón depende del radio en el plano del aro, no de una dirección
+    fn bakeable(&self) -> bool {
+        false
+    }
+}
 
-    surface_value = (surface_value + fine_texture).clamp(0.0, 1.0);
 
-    // Efecto de luz direccional y sombras profundas
-    let light_angle = (position.y * 0.6 + uniforms.time as f32 * 0.001).sin() * 0.2 + 0.8;
-    let directional_light = (position.x * 0.25 + uniforms.time as f32 * 0.002).cos() * 0.15 + 1.0;
-    let final_light_intensity = light_angle * directional_light;
+pub struct RockyMaterial {
+    pub mountain_color: Color,
+    pub valley_color: Color,
+    pub deep_color: Color,
+    pub terrain_scale: f32,
+    pub fine_scale: f32,
+    // Cráteres: frecuencia baja para que queden dispersos
+    pub crater_frequency: f32,
+    pub crater_depth: f32,
+    // Por encima de este valor empiezan las montañas
+    pub mountain_threshold: f32,
+    pub specular: Specular,
+}
 
-    // Selección de color en función de la altura y la luz
-    let base_color = if surface_value > 0.6 {
-        mountain_color.lerp(&valley_color, (surface_value - 0.6) * 1.5)
-    } else {
-        deep_color.lerp(&valley_color, surface_value * 1.8)
-    };
+impl Default for RockyMaterial {
+    fn default() -> Self {
+        RockyMaterial {
+            mountain_color: Color::new(228, 179, 85),
+            valley_color: Color::new(195, 126, 50),
+            deep_color: Color::new(92, 51, 22),
+            terrain_scale: 800.0,
+            fine_scale: 2000.0,
+            crater_frequency: 0.3,
+            crater_depth: 3.0,
+            mountain_threshold: 0.6,
+            specular: ROCK_SPECULAR,
+        }
+    }
+}
 
-    // Aplicar intensidad de luz y sombras
-    let mut final_color = base_color * final_light_intensity;
+impl Material for RockyMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    // Agregar variaciones de textura adicionales para simular desgaste
-    let shadow_noise = uniforms.noise.get_noise_3d(
-        position.x * 3000.0,
-        position.y * 3000.0,
-        position.z * 3000.0,
-    ) * 0.3;
-    let highlight_noise = uniforms.noise.get_noise_3d(
-        position.x * 3500.0,
-        position.y * 3500.0,
-        position.z * 3500.0,
-    ) * 0.2;
+        // Elevación del terreno con una segunda capa desplazada
+        let base_noise = sample(uniforms, &position, self.terrain_scale);
+        let terrain_variation = sample(uniforms, &position.add_scalar(1000.0), self.terrain_scale * 0.8) * 0.6;
+        let combined_terrain_value = (base_noise * 0.7 + terrain_variation * 0.3).clamp(0.0, 1.0);
 
-    final_color = final_color * (1.0 + shadow_noise + highlight_noise);
+        let crater_effect = ((position.x * self.crater_frequency).sin() * (position.y * self.crater_frequency).cos()).abs() * self.crater_depth;
+        let mut surface_value = (combined_terrain_value - crater_effect).clamp(0.0, 1.0);
 
-    // Efecto de variación de profundidad y intensidad adicional
-    let depth_variation = uniforms.noise.get_noise_3d(
-        position.x * 4000.0,
-        position.y * 4000.0,
-        position.z * 4000.0,
-    ) * 0.15;
-    final_color = final_color * (1.0 + depth_variation);
+        // Ruido fino para detalles en la superficie
+        let fine_texture = sample(uniforms, &position, self.fine_scale) * 0.35;
+        surface_value = (surface_value + fine_texture).clamp(0.0, 1.0);
 
-    final_color * fragment.intensity
-}
+        let base_color = if surface_value > self.mountain_threshold {
+            self.mountain_color.lerp(&self.valley_color, (surface_value - self.mountain_threshold) * 1.5)
+        } else {
+            self.deep_color.lerp(&self.valley_color, surface_value * 1.8)
+        };
 
+        // Variaciones de textura adicionales para simular desgaste
+        let shadow_noise = sample(uniforms, &position, 3000.0) * 0.3;
+        let highlight_noise = sample(uniforms, &position, 3500.0) * 0.2;
+        let depth_variation = sample(uniforms, &position, 4000.0) * 0.15;
 
+        Surface::new(base_color * (1.0 + shadow_noise + highlight_noise) * (1.0 + depth_variation))
+    }
 
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular).apply(surface.albedo, surface.specular_mask)
+    }
+}
 
 
-pub fn gas_giant_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let color_palette = [
-        Vec3::new(235.0 / 255.0, 91.0 / 255.0, 96.0 / 255.0),
-        Vec3::new(237.0 / 255.0, 112.0 / 255.0, 47.0 / 255.0),
-        Vec3::new(234.0 / 255.0, 116.0 / 255.0, 92.0 / 255.0),
-        Vec3::new(235.0 / 255.0, 91.0 / 255.0, 181.0 / 255.0),
-        Vec3::new(235.0 / 255.0, 165.0 / 255.0, 91.0 / 255.0),
-    ];
+// Tormenta ovalada que deriva con el viento de su latitud y arrastra las bandas en espiral
+#[derive(Debug, Clone, Copy)]
+pub struct Storm {
+    // Centro en radianes al empezar el reloj
+    pub latitude: f32,
+    pub longitude: f32,
+    // Semieje norte-sur en radianes y cuántas veces más ancho es de este a oeste
+    pub radius: f32,
+    pub elongation: f32,
+    // Color del óvalo en sRGB
+    pub color: Vec3,
+    // Giro de las bandas en el centro, en radianes; el signo da el sentido
+    pub spin: f32,
+}
 
-    let noise_scale = 5.0; // Escala del ruido para las variaciones
-    let noise_variation = uniforms.noise.get_noise_2d(
-        fragment.vertex_position.x * noise_scale,
-        fragment.vertex_position.y * noise_scale,
-    );
+// Bandas de gas a lo largo de un eje del modelo
+pub struct GasGiantMaterial {
+    // Colores en sRGB, repartidos de forma pareja de una banda a la siguiente
+    pub palette: Vec<Vec3>,
+    // Las bandas son paralelas al ecuador del modelo, que gira sobre su eje y
+    pub band_frequency: f32,
+    // Rotación diferencial (radianes por segundo, relativa al giro del planeta):
+    // velocidad en el ecuador más chorros que alternan de sentido con la latitud
+    pub equator_speed: f32,
+    pub jet_speed: f32,
+    pub jet_count: f32,
+    // Remolinos de ruido curl entre bandas: desplazamiento en radianes y escala
+    pub turbulence: f32,
+    pub turbulence_scale: f32,
+    // Vetas alargadas de este a oeste dentro de cada banda
+    pub streak_scale: f32,
+    pub streak_strength: f32,
+    pub storms: Vec<Storm>,
+}
 
-    // Generar patrón de bandas y variaciones para simular la textura gaseosa
-    let stripe_pattern = (fragment.vertex_position.y * 8.0 + noise_variation * 1.5).sin();
-    let color_index = ((stripe_pattern + 1.0) / 2.0 * (color_palette.len() as f32)) as usize % color_palette.len();
-    let mut gas_color = color_palette[color_index] * (1.0 + noise_variation * 0.1);
-
-    // Ajuste de intensidad para efectos de iluminación
-    gas_color = gas_color * fragment.intensity;
-
-    Color::new(
-        (gas_color.x * 255.0) as u8,
-        (gas_color.y * 255.0) as u8,
-        (gas_color.z * 255.0) as u8,
-    )
-}
-
-pub fn gas_giant_shader2(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let color_palette = [
-        Vec3::new(98.0 / 255.0, 68.0 / 255.0, 217.0 / 255.0),
-        Vec3::new(159.0 / 255.0, 133.0 / 255.0, 236.0 / 255.0),
-        Vec3::new(84.0 / 255.0, 64.0 / 255.0, 140.0 / 255.0),
-        Vec3::new(156.0 / 255.0, 100.0 / 255.0, 140.0 / 255.0),
-        Vec3::new(204.0 / 255.0, 177.0 / 255.0, 210.0 / 255.0),
-        Vec3::new(61.0 / 255.0, 42.0 / 255.0, 80.0 / 255.0),
-    ];
-
-    let noise_scale = 5.0; // Escala del ruido para las variaciones
-    let noise_variation = uniforms.noise.get_noise_2d(
-        fragment.vertex_position.x * noise_scale,
-        fragment.vertex_position.y * noise_scale,
-    );
+impl GasGiantMaterial {
+    // Variante violeta, la del gigante con aro, con un óvalo claro en el norte
+    pub fn violet() -> Self {
+        GasGiantMaterial {
+            palette: vec![
+                Vec3::new(98.0 / 255.0, 68.0 / 255.0, 217.0 / 255.0),
+                Vec3::new(159.0 / 255.0, 133.0 / 255.0, 236.0 / 255.0),
+                Vec3::new(84.0 / 255.0, 64.0 / 255.0, 140.0 / 255.0),
+                Vec3::new(156.0 / 255.0, 100.0 / 255.0, 140.0 / 255.0),
+                Vec3::new(204.0 / 255.0, 177.0 / 255.0, 210.0 / 255.0),
+                Vec3::new(61.0 / 255.0, 42.0 / 255.0, 80.0 / 255.0),
+            ],
+            band_frequency: 11.0,
+            storms: vec![Storm {
+                latitude: 0.55,
+                longitude: 2.0,
+                radius: 0.09,
+                elongation: 1.6,
+                color: Vec3::new(0.92, 0.88, 0.96),
+                spin: -2.5,
+            }],
+            ..GasGiantMaterial::default()
+        }
+    }
 
-    // Generar patrón de bandas verticales y variaciones para simular la textura gaseosa
-    let stripe_pattern = (fragment.vertex_position.x * 8.0 + noise_variation * 1.5).sin();
-    let color_index = ((stripe_pattern + 1.0) / 2.0 * (color_palette.len() as f32)) as usize % color_palette.len();
-    let mut gas_color = color_palette[color_index] * (1.0 + noise_variation * 0.1);
+    fn wind(&self, latitude: f32) -> f32 {
+        self.equator_speed + self.jet_speed * (latitude * self.jet_count).cos()
+    }
 
-    // Ajuste de intensidad para efectos de iluminación
-    gas_color = gas_color * fragment.intensity;
+    // Color de bandas para (latitud, longitud) ya giradas por el viento
+    fn bands(&self, uniforms: &Uniforms, latitude: f32, longitude: f32) -> Vec3 {
+        let point = sphere_point(latitude, longitude);
+
+        // Curl de un potencial de ruido sobre la esfera: remolinos sin fuentes ni sumideros
+        let east = Vec3::new(longitude.cos(), 0.0, -longitude.sin());
+        let north = point.cross(&east);
+        let epsilon = 1e-3;
+        let potential = |offset: Vec3| sample(uniforms, &(point + offset * epsilon), self.turbulence_scale);
+        let d_east = (potential(east) - potential(-east)) / (2.0 * epsilon * self.turbulence_scale);
+        let d_north = (potential(north) - potential(-north)) / (2.0 * epsilon * self.turbulence_scale);
+
+        // Más revuelto en los bordes, donde las bandas vecinas se cortan
+        let shear = (latitude * self.band_frequency).cos().abs();
+        let latitude = latitude - d_east * self.turbulence * shear;
+        let longitude = longitude + d_north * self.turbulence * shear / latitude.cos().max(0.2);
+
+        let warped = sphere_point(latitude, longitude);
+        let streak_point = warped.component_mul(&Vec3::new(0.25, 1.0, 0.25));
+        let streaks = sample(uniforms, &streak_point, self.streak_scale);
+
+        let stripe = (latitude * self.band_frequency + streaks * 0.3).sin() * 0.5 + 0.5;
+        palette_gradient(&self.palette, stripe) * (1.0 + streaks * self.streak_strength)
+    }
+}
 
-    Color::new(
-        (gas_color.x * 255.0) as u8,
-        (gas_color.y * 255.0) as u8,
-        (gas_color.z * 255.0) as u8,
-    )
+impl Default for GasGiantMaterial {
+    // Bandas cálidas con una gran mancha rojiza en el sur
+    fn default() -> Self {
+        GasGiantMaterial {
+            palette: warm_bands(),
+            band_frequency: 8.0,
+            equator_speed: 0.04,
+            jet_speed: 0.05,
+            jet_count: 9.0,
+            turbulence: 0.5,
+            turbulence_scale: 250.0,
+            streak_scale: 400.0,
+            streak_strength: 0.12,
+            storms: vec![Storm {
+                latitude: -0.38,
+                longitude: 0.6,
+                radius: 0.12,
+                elongation: 1.8,
+                color: Vec3::new(0.72, 0.26, 0.18),
+                spin: 3.0,
+            }],
+        }
+    }
 }
 
-pub fn volcanic_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    // Colores para el terreno volcánico
-    let lava_color = Color::new(230, 55, 15);
-    let rock_color = Color::new(80, 30, 20);
-    let ash_color = Color::new(100, 40, 35);
+impl Material for GasGiantMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let direction = fragment.vertex_position.normalize();
+        let latitude = direction.y.clamp(-1.0, 1.0).asin();
+        let longitude = direction.x.atan2(direction.z);
+
+        // Las tormentas retuercen las bandas a su alrededor y se pintan encima. Cada
+        // una se mide desde el punto original; sus giros y sus óvalos se suman
+        let (mut twist_latitude, mut twist_longitude) = (0.0, 0.0);
+        let (mut storm_color, mut storm_cover) = (Vec3::zeros(), 0.0);
+        for storm in &self.storms {
+            let center = storm.longitude + self.wind(storm.latitude) * uniforms.time;
+            let width = storm.radius * storm.elongation;
+            let dx = wrap_angle(longitude - center) * latitude.cos() / width;
+            let dy = (latitude - storm.latitude) / storm.radius;
+            let r = (dx * dx + dy * dy).sqrt();
+            if r >= 2.0 {
+                continue;
+            }
+
+            let (sin, cos) = (storm.spin * (1.0 - r / 2.0).powi(2)).sin_cos();
+            let (dx, dy) = (dx * cos - dy * sin, dx * sin + dy * cos);
+            let twisted = storm.latitude + dy * storm.radius;
+            twist_latitude += twisted - latitude;
+            twist_longitude += wrap_angle(center + dx * width / twisted.cos().max(0.05) - longitude);
+
+            // Anillos tenues dentro del óvalo
+            let cover = 1.0 - smoothstep(0.6, 1.0, r);
+            storm_color += storm.color * (0.9 + 0.1 * (r * 9.0).cos()) * cover;
+            storm_cover += cover;
+        }
+
+        // Cada latitud gira a su velocidad y arrastra el ruido consigo
+        let latitude = latitude + twist_latitude;
+        let longitude = longitude + twist_longitude - self.wind(latitude) * uniforms.time;
+        let mut color = self.bands(uniforms, latitude, longitude);
+        if storm_cover > 0.0 {
+            color = color.lerp(&(storm_color / storm_cover), storm_cover.min(1.0));
+        }
+
+        Surface::new(Color::from_srgb_vec3(color))
+    }
 
-    // Posición del fragmento y escalas de ruido
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let noise_scale = 700.0;
-    let lava_scale = 150.0;
-    let fine_detail_scale = 3000.0;
-
-    // Ruido básico de terreno para simular variaciones de altura en la superficie volcánica
-    let terrain_noise = uniforms.noise.get_noise_3d(
-        position.x * noise_scale,
-        position.y * noise_scale,
-        position.z * noise_scale,
-    );
+    fn light(&self, surface: &Surface, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+        surface.albedo * fragment.intensity
+    }
 
-    // Ruido adicional para simular lagos o grietas de lava
-    let lava_noise = uniforms.noise.get_noise_3d(
-        position.x * lava_scale,
-        position.y * lava_scale,
-        position.z * lava_scale,
-    );
+    // Las bandas se mueven con el reloj
+    fn bakeable(&self) -> bool {
+        false
+    }
+}
 
-    // Condición para identificar áreas de lava: aquellas con ruido bajo
-    let is_lava = lava_noise < -0.2;
+// Punto de la esfera unitaria con la longitud medida desde +z hacia +x
+fn sphere_point(latitude: f32, longitude: f32) -> Vec3 {
+    Vec3::new(latitude.cos() * longitude.sin(), latitude.sin(), latitude.cos() * longitude.cos())
+}
 
-    // Ruido fino para añadir textura en las rocas volcánicas y cenizas
-    let fine_detail = uniforms.noise.get_noise_3d(
-        position.x * fine_detail_scale,
-        position.y * fine_detail_scale,
-        position.z * fine_detail_scale,
-    ) * 0.3;
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
 
-    // Valor combinado de terreno, lava y detalle fino
-    let combined_value = (terrain_noise + fine_detail).clamp(0.0, 1.0);
+// Interpolación lineal entre colores repartidos en [0, 1]
+fn palette_gradient(palette: &[Vec3], t: f32) -> Vec3 {
+    match palette.len() {
+        0 => Vec3::zeros(),
+        1 => palette[0],
+        len => {
+            let position = t.clamp(0.0, 1.0) * (len - 1) as f32;
+            let index = (position as usize).min(len - 2);
+            palette[index].lerp(&palette[index + 1], position - index as f32)
+        }
+    }
+}
 
-    // Aplicar un color de lava o roca en función de la posición en el terreno
-    let base_color = if is_lava {
-        lava_color * (1.0 + (uniforms.time as f32 * 0.02).sin().abs() * 0.5)
-    } else if combined_value > 0.7 {
-        rock_color.lerp(&ash_color, (combined_value - 0.7) * 1.5)
-    } else {
-        ash_color * combined_value
-    };
 
-    // Intensidad de luz para generar sombras y detalles
-    let light_angle = (position.y * 0.5 + uniforms.time as f32 * 0.002).sin() * 0.4 + 0.6;
-    let directional_light = (position.x * 0.25 + uniforms.time as f32 * 0.002).cos() * 0.15 + 1.0;
-    let final_light_intensity = light_angle * directional_light;
+pub struct VolcanicMaterial {
+    pub lava_color: Color,
+    pub rock_color: Color,
+    pub ash_color: Color,
+    pub terrain_scale: f32,
+    pub lava_scale: f32,
+    pub fine_scale: f32,
+    // Las zonas con ruido de lava por debajo de este valor son lagos de lava
+    pub lava_threshold: f32,
+    // Emisión HDR de la lava, por encima del blanco de pantalla
+    pub lava_emission: f32,
+    pub specular: Specular,
+}
 
-    let mut final_color = base_color * final_light_intensity;
+impl Default for VolcanicMaterial {
+    fn default() -> Self {
+        VolcanicMaterial {
+            lava_color: Color::new(230, 55, 15),
+            rock_color: Color::new(80, 30, 20),
+            ash_color: Color::new(100, 40, 35),
+            terrain_scale: 700.0,
+            lava_scale: 150.0,
+            fine_scale: 3000.0,
+            lava_threshold: -0.2,
+            lava_emission: 1.8,
+            specular: ROCK_SPECULAR,
+        }
+    }
+}
 
-    let shadow_noise = uniforms.noise.get_noise_3d(
-        position.x * 3500.0,
-        position.y * 3500.0,
-        position.z * 3500.0,
-    ) * 0.2;
-    let highlight_noise = uniforms.noise.get_noise_3d(
-        position.x * 4000.0,
-        position.y * 4000.0,
-        position.z * 4000.0,
-    ) * 0.2;
+impl Material for VolcanicMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    final_color = final_color * (1.0 + shadow_noise + highlight_noise);
+        let terrain_noise = sample(uniforms, &position, self.terrain_scale);
+        let lava_noise = sample(uniforms, &position, self.lava_scale);
+        let is_lava = lava_noise < self.lava_threshold;
 
-    final_color * fragment.intensity
-}
+        // Textura de rocas volcánicas y cenizas
+        let fine_detail = sample(uniforms, &position, self.fine_scale) * 0.3;
+        let combined_value = (terrain_noise + fine_detail).clamp(0.0, 1.0);
 
+        let base_color = if is_lava {
+            self.lava_color
+        } else if combined_value > 0.7 {
+            self.rock_color.lerp(&self.ash_color, (combined_value - 0.7) * 1.5)
+        } else {
+            self.ash_color * combined_value
+        };
 
+        let shadow_noise = sample(uniforms, &position, 3500.0) * 0.2;
+        let highlight_noise = sample(uniforms, &position, 4000.0) * 0.2;
+        let surface = Surface::new(base_color * (1.0 + shadow_noise + highlight_noise));
 
-pub fn icy_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    // Colores de la superficie de hielo
-    let ice_color = Color::new(220, 240, 255);  
-    let frost_color = Color::new(150, 200, 255); 
-    let deep_ice_color = Color::new(100, 180, 240);
+        // La lava no refleja y emite luz propia por encima del blanco de pantalla (HDR)
+        if is_lava {
+            surface
+                .with_specular_mask(0.0)
+                .with_emission(self.lava_color.to_vec3() * self.lava_emission)
+                .with_layer(1.0)
+        } else {
+            surface
+        }
+    }
 
-    // Escalas de ruido para texturizar el hielo
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let ice_noise_scale = 800.0;
-    let crack_scale = 200.0;    
-    let fine_scale = 3000.0;
-
-    let base_ice_noise = uniforms.noise.get_noise_3d(
-        position.x * ice_noise_scale,
-        position.y * ice_noise_scale,
-        position.z * ice_noise_scale,
-    );
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        // La lava late con el tiempo
+        let pulse = 1.0 + surface.layer * (uniforms.time * 0.02).sin().abs() * 0.5;
+        let color = surface.albedo * pulse;
+        let lit_color = blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular).apply(color, surface.specular_mask);
+        lit_color + Color::from_vec3(surface.emission)
+    }
+}
 
-    let crack_noise = uniforms.noise.get_noise_3d(
-        position.x * crack_scale,
-        position.y * crack_scale,
-        position.z * crack_scale,
-    );
 
-    let is_crack_or_water = crack_noise < -0.1;
+pub struct IcyMaterial {
+    pub ice_color: Color,
+    pub frost_color: Color,
+    pub deep_ice_color: Color,
+    pub ice_scale: f32,
+    pub crack_scale: f32,
+    pub fine_scale: f32,
+    // Grietas con agua donde el ruido de grietas baja de este valor
+    pub crack_threshold: f32,
+    // Por encima de este valor la superficie es escarcha
+    pub frost_threshold: f32,
+    pub specular: Specular,
+}
 
-    let fine_detail = uniforms.noise.get_noise_3d(
-        position.x * fine_scale,
-        position.y * fine_scale,
-        position.z * fine_scale,
-    ) * 0.2;
+impl Default for IcyMaterial {
+    fn default() -> Self {
+        IcyMaterial {
+            ice_color: Color::new(220, 240, 255),
+            frost_color: Color::new(150, 200, 255),
+            deep_ice_color: Color::new(100, 180, 240),
+            ice_scale: 800.0,
+            crack_scale: 200.0,
+            fine_scale: 3000.0,
+            crack_threshold: -0.1,
+            frost_threshold: 0.6,
+            specular: ICE_SPECULAR,
+        }
+    }
+}
 
-    let combined_value = (base_ice_noise + fine_detail).clamp(0.0, 1.0);
+impl Material for IcyMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    let base_color = if is_crack_or_water {
-        deep_ice_color * (1.0 + (uniforms.time as f32 * 0.02).sin().abs() * 0.3)
-    } else if combined_value > 0.6 {
-        frost_color.lerp(&ice_color, (combined_value - 0.6) * 1.5)
-    } else {
-        ice_color * (combined_value + 1.2)
-    };
+        let base_ice_noise = sample(uniforms, &position, self.ice_scale);
+        let crack_noise = sample(uniforms, &position, self.crack_scale);
+        let is_crack_or_water = crack_noise < self.crack_threshold;
 
+        let fine_detail = sample(uniforms, &position, self.fine_scale) * 0.2;
+        let combined_value = (base_ice_noise + fine_detail).clamp(0.0, 1.0);
 
+        let base_color = if is_crack_or_water {
+            self.deep_ice_color
+        } else if combined_value > self.frost_threshold {
+            self.frost_color.lerp(&self.ice_color, (combined_value - self.frost_threshold) * 1.5)
+        } else {
+            self.ice_color * (combined_value + 1.2)
+        };
 
-    let light_angle = (position.y * 0.5 + uniforms.time as f32 * 0.001).sin() * 0.3 + 0.7;
-    let directional_light = (position.x * 0.25 + uniforms.time as f32 * 0.002).cos() * 0.15 + 1.0;
+        let shadow_noise = sample(uniforms, &position, 3500.0) * 0.2;
+        let highlight_noise = sample(uniforms, &position, 4000.0) * 0.1;
+        let surface = Surface::new(base_color * (1.0 + shadow_noise + highlight_noise));
 
-    let mut final_light_intensity = light_angle * directional_light;
-    if final_light_intensity < 0.5 {
-        final_light_intensity = 0.5;
+        // Las grietas con agua brillan más que la escarcha
+        if is_crack_or_water {
+            surface.with_layer(1.0)
+        } else {
+            surface.with_specular_mask(0.5 + combined_value * 0.5)
+        }
     }
-    
 
-    let mut final_color = base_color * final_light_intensity;
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        // El agua de las grietas late con el tiempo
+        let pulse = 1.0 + surface.layer * (uniforms.time * 0.02).sin().abs() * 0.3;
+        let color = surface.albedo * pulse;
+        blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular).apply(color, surface.specular_mask)
+    }
+}
 
-    let shadow_noise = uniforms.noise.get_noise_3d(
-        position.x * 3500.0,
-        position.y * 3500.0,
-        position.z * 3500.0,
-    ) * 0.2;
-    let highlight_noise = uniforms.noise.get_noise_3d(
-        position.x * 4000.0,
-        position.y * 4000.0,
-        position.z * 4000.0,
-    ) * 0.1;
 
-    final_color = final_color * (1.0 + shadow_noise + highlight_noise);
+pub struct DesertMaterial {
+    // De la arena más oscura a la más rosada
+    pub sands: [Color; 5],
+    pub dune_scale: f32,
+    pub fine_scale: f32,
+    // Rango al que se limita el valor de las dunas antes de elegir el color
+    pub dune_range: (f32, f32),
+    pub specular: Specular,
+}
 
-    final_color * fragment.intensity
+impl Default for DesertMaterial {
+    fn default() -> Self {
+        DesertMaterial {
+            sands: [
+                Color::new(106, 63, 54),
+                Color::new(200, 167, 150),
+                Color::new(244, 243, 238),
+                Color::new(248, 228, 190),
+                Color::new(238, 169, 136),
+            ],
+            dune_scale: 500.0,
+            fine_scale: 2000.0,
+            dune_range: (2.0, 5.0),
+            specular: SAND_SPECULAR,
+        }
+    }
 }
 
+impl Material for DesertMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let [dark, medium, light, pale, pink] = &self.sands;
+        let position = fragment.vertex_position;
 
+        let dune_noise = sample(uniforms, &position, self.dune_scale);
+        let dune_variation = sample(uniforms, &position.add_scalar(300.0), self.dune_scale * 0.5) * 0.6;
+        let combined_dune_value = (dune_noise * 0.7 + dune_variation * 0.3).clamp(0.0, 1.0);
 
+        let fine_sand_texture = sample(uniforms, &position, self.fine_scale) * 0.2;
+        let final_dune_value = (combined_dune_value + fine_sand_texture).clamp(self.dune_range.0, self.dune_range.1);
 
+        let base_color = if final_dune_value > 0.8 {
+            dark.lerp(medium, (final_dune_value - 0.8) * 5.0)
+        } else if final_dune_value > 0.6 {
+            medium.lerp(light, (final_dune_value - 0.6) * 5.0)
+        } else if final_dune_value > 0.4 {
+            light.lerp(pale, (final_dune_value - 0.4) * 5.0)
+        } else {
+            pale.lerp(pink, final_dune_value * 2.5)
+        };
 
-pub fn desert_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    // Colores para las variaciones de arena
-    let dark_sand_color = Color::new(106, 63, 54);
-    let medium_sand_color = Color::new(200, 167, 150);
-    let light_sand_color = Color::new(244, 243, 238);
-    let pale_sand_color = Color::new(248, 228, 190);
-    let pink_sand_color = Color::new(238, 169, 136);
+        let shadow_texture = sample(uniforms, &position, 3000.0) * 0.2;
+        let dune_depth_variation = sample(uniforms, &position, 4000.0) * 0.15;
 
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let base_scale = 500.0;
+        Surface::new(base_color * (1.0 + shadow_texture) * (1.0 + dune_depth_variation))
+    }
 
-    let dune_noise = uniforms.noise.get_noise_3d(
-        position.x * base_scale,
-        position.y * base_scale,
-        position.z * base_scale,
-    );
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular).apply(surface.albedo, surface.specular_mask)
+    }
+}
 
-    let dune_variation = uniforms.noise.get_noise_3d(
-        (position.x + 300.0) * base_scale * 0.5,
-        (position.y + 300.0) * base_scale * 0.5,
-        (position.z + 300.0) * base_scale * 0.5,
-    ) * 0.6;
 
-    let combined_dune_value = (dune_noise * 0.7 + dune_variation * 0.3).clamp(0.0, 1.0);
+// Tierras que asoman sobre el agua, con ciudades en la costa
+pub struct Islands {
+    pub beach_color: Color,
+    pub lowland_color: Color,
+    pub highland_color: Color,
+    // Fbm de la elevación
+    pub fractal: Fractal,
+    // Valor del fbm que queda al nivel del mar
+    pub sea_level: f32,
+    pub cities: CityLights,
+}
+
+impl Default for Islands {
+    fn default() -> Self {
+        Islands {
+            beach_color: Color::new(214, 196, 150),
+            lowland_color: Color::new(64, 118, 52),
+            highland_color: Color::new(112, 96, 70),
+            fractal: Fractal::new(160.0, 5, 2.0, 0.5),
+            sea_level: 0.12,
+            cities: CityLights::default(),
+        }
+    }
+}
+
+impl Islands {
+    // Elevación sobre el nivel del mar; negativa en el agua
+    pub fn elevation(&self, uniforms: &Uniforms, position: &Vec3) -> f32 {
+        uniforms.noise.fractal(position, self.fractal, |n| n) - self.sea_level
+    }
+
+    // Luces de las ciudades en tierra firme, ya encendidas según el terminador
+    pub fn night_lights(&self, fragment: &Fragment, night: Vec3) -> Color {
+        Color::from_vec3(night * night_side(fragment, self.cities.terminator))
+    }
 
-    let fine_sand_texture = uniforms.noise.get_noise_3d(
-        position.x * 2000.0,
-        position.y * 2000.0,
-        position.z * 2000.0,
-    ) * 0.2;
+    fn color(&self, elevation: f32) -> Color {
+        if elevation < 0.02 {
+            self.beach_color
+        } else {
+            self.lowland_color.lerp(&self.highland_color, ((elevation - 0.02) * 3.0).clamp(0.0, 1.0))
+        }
+    }
+}
 
-    let final_dune_value = (combined_dune_value + fine_sand_texture).clamp(2.0, 5.0);
+pub struct WaterMaterial {
+    pub shallow_color: Color,
+    pub deep_color: Color,
+    pub floor_color: Color,
+    pub depth_scale: f32,
+    // Por encima de este valor el agua es poco profunda
+    pub shallow_threshold: f32,
+    // Brillo de la tierra firme; el agua se ilumina con `ocean`
+    pub specular: Specular,
+    pub ocean: Ocean,
+    // Sin islas es un océano de punta a punta
+    pub islands: Option<Islands>,
+}
 
-    let light_angle = (position.y * 0.6 + uniforms.time as f32 * 0.001).sin() * 0.2 + 0.8;
-    let directional_light = (position.x * 0.25 + uniforms.time as f32 * 0.002).cos() * 0.15 + 1.0;
-    let final_light_intensity = light_angle * directional_light;
+impl Default for WaterMaterial {
+    fn default() -> Self {
+        WaterMaterial {
+            shallow_color: Color::new(85, 170, 255),
+            deep_color: Color::new(10, 50, 120),
+            floor_color: Color::new(0, 25, 80),
+            depth_scale: 300.0,
+            shallow_threshold: 0.5,
+            specular: WATER_SPECULAR,
+            ocean: Ocean::default(),
+            islands: None,
+        }
+    }
+}
 
-    let base_color = if final_dune_value > 0.8 {
-        dark_sand_color.lerp(&medium_sand_color, (final_dune_value - 0.8) * 5.0)
-    } else if final_dune_value > 0.6 {
-        medium_sand_color.lerp(&light_sand_color, (final_dune_value - 0.6) * 5.0)
-    } else if final_dune_value > 0.4 {
-        light_sand_color.lerp(&pale_sand_color, (final_dune_value - 0.4) * 5.0)
-    } else {
-        pale_sand_color.lerp(&pink_sand_color, final_dune_value * 2.5)
-    };
+impl WaterMaterial {
+    // Planeta habitable: el mismo océano con islas y ciudades que se ven de noche
+    pub fn habitable() -> Self {
+        WaterMaterial { islands: Some(Islands::default()), ..WaterMaterial::default() }
+    }
+}
 
-    let mut final_color = base_color * final_light_intensity;
+impl Material for WaterMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    let shadow_texture = uniforms.noise.get_noise_3d(
-        position.x * 3000.0,
-        position.y * 3000.0,
-        position.z * 3000.0,
-    ) * 0.2;
+        let base_noise = sample(uniforms, &position, self.depth_scale);
+        let depth_variation = sample(uniforms, &position.add_scalar(500.0), self.depth_scale * 0.4) * 0.5;
+        let water_depth_value = (base_noise * 0.6 + depth_variation * 0.4).clamp(0.0, 1.0);
 
-    final_color = final_color * (1.0 + shadow_texture);
+        let base_color = if water_depth_value > self.shallow_threshold {
+            self.shallow_color.lerp(&self.deep_color, (water_depth_value - self.shallow_threshold) * 2.0)
+        } else {
+            self.deep_color.lerp(&self.floor_color, water_depth_value * 2.0)
+        };
 
-    let dune_depth_variation = uniforms.noise.get_noise_3d(
-        position.x * 4000.0,
-        position.y * 4000.0,
-        position.z * 4000.0,
-    ) * 0.15;
-    final_color = final_color * (1.0 + dune_depth_variation);
+        let shadow_noise = sample(uniforms, &position, 2500.0) * 0.25;
+        let highlight_noise = sample(uniforms, &position, 3500.0) * 0.15;
+        let extra_depth_variation = sample(uniforms, &position, 5000.0) * 0.1;
+
+        // Tierra firme: sin reflejo y con las ciudades de noche
+        if let Some(islands) = &self.islands {
+            let elevation = islands.elevation(uniforms, &position);
+            if elevation > 0.0 {
+                return Surface::new(islands.color(elevation) * (1.0 + shadow_noise * 0.5))
+                    .with_specular_mask(0.05)
+                    .with_night(islands.cities.emission(&position, elevation, uniforms.noise));
+            }
+        }
+
+        // La capa marca el agua, que se ilumina con las olas
+        Surface::new(base_color * (1.0 + shadow_noise + highlight_noise) * (1.0 + extra_depth_variation))
+            .with_layer(1.0)
+    }
 
-    final_color * fragment.intensity
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let mut lit_color = blinn_phong(fragment, &fragment.normal, uniforms.lights, &self.specular).apply(surface.albedo, surface.specular_mask);
+
+        // En un mapa horneado la costa queda a medias entre tierra y agua
+        if surface.layer > 0.0 {
+            let depth = self.islands.as_ref().map_or(f32::INFINITY, |islands| -islands.elevation(uniforms, &fragment.vertex_position));
+            let water = self.ocean.light(surface.albedo, depth, surface.specular_mask, fragment, uniforms);
+            lit_color = lit_color.lerp(&water, surface.layer);
+        }
+
+        // Las ciudades se encienden al cruzar el terminador
+        match &self.islands {
+            Some(islands) => lit_color + islands.night_lights(fragment, surface.night),
+            None => lit_color,
+        }
+    }
 }
 
 
-pub fn water_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let shallow_water_color = Color::new(85, 170, 255);
-    let deep_water_color = Color::new(10, 50, 120);
-    let ocean_floor_color = Color::new(0, 25, 80);
-
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let base_scale = 300.0;
+pub struct MoonMaterial {
+    // Tonos de la superficie, del más oscuro al más claro
+    pub palette: [Color; 5],
+    pub noise_scale: f32,
+    pub fine_scale: f32,
+    // Rango al que se limita el valor de la superficie antes de elegir el tono
+    pub surface_range: (f32, f32),
+    pub craters: Craters,
+}
 
-    let base_noise = uniforms.noise.get_noise_3d(
-        position.x * base_scale,
-        position.y * base_scale,
-        position.z * base_scale,
-    );
+impl Default for MoonMaterial {
+    fn default() -> Self {
+        MoonMaterial {
+            palette: [
+                Color::new(247, 225, 195),
+                Color::new(248, 228, 201),
+                Color::new(249, 231, 207),
+                Color::new(249, 234, 213),
+                Color::new(250, 237, 219),
+            ],
+            noise_scale: 600.0,
+            fine_scale: 1200.0,
+            surface_range: (1.0, 2.0),
+            craters: Craters::default(),
+        }
+    }
+}
 
-    let depth_variation = uniforms.noise.get_noise_3d(
-        (position.x + 500.0) * base_scale * 0.4,
-        (position.y + 500.0) * base_scale * 0.4,
-        (position.z + 500.0) * base_scale * 0.4,
-    ) * 0.5;
+impl MoonMaterial {
+    // Cráteres más una rugosidad fina que no se nota en el color
+    fn height(&self, uniforms: &Uniforms, position: &Vec3) -> f32 {
+        let roughness = sample(uniforms, position, self.fine_scale) * 0.0004;
+        self.craters.height(position) + roughness
+    }
+}
 
-    let combined_depth_value = (base_noise * 0.6 + depth_variation * 0.4).clamp(0.0, 1.0);
+impl Material for MoonMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    let ocean_depth = 0.4;
-    let ocean_depth_effect = ((position.x * ocean_depth).sin() * (position.y * ocean_depth).cos()).abs() * 2.0;
+        let base_noise = sample(uniforms, &position, self.noise_scale);
+        let fine_detail = sample(uniforms, &position, self.fine_scale) * 0.3;
+        let combined_value = (base_noise + fine_detail).clamp(self.surface_range.0, self.surface_range.1);
 
-    let mut water_depth_value = (combined_depth_value - ocean_depth_effect).clamp(0.0, 1.0);
+        // Un tono por cada quinto de [0, 1]
+        let index = ((combined_value / 0.2).max(0.0) as usize).min(self.palette.len() - 1);
+        let base_color = self.palette[index] * combined_value;
 
-    let fine_wave_texture = uniforms.noise.get_noise_3d(
-        position.x * 500.0,
-        position.y * 500.0,
-        position.z * 500.0,
-    ) * 5.5;
-    
+        // Eyecta y rayos de los cráteres jóvenes aclaran el suelo
+        let craters = self.craters.sample(&position);
+        let shadow_noise = sample(uniforms, &position, 2000.0) * 0.1;
+        let highlight_noise = sample(uniforms, &position, 2500.0) * 0.05;
 
-    water_depth_value = (water_depth_value + fine_wave_texture).clamp(0.0, 1.0);
+        Surface::new(base_color * (1.0 + shadow_noise + highlight_noise + craters.brightness)).with_specular_mask(0.0)
+    }
 
-    let light_angle = (position.y * 0.6 + uniforms.time as f32 * 0.001).sin() * 0.2 + 0.8;
-    let directional_light = (position.x * 0.25 + uniforms.time as f32 * 0.002).cos() * 0.15 + 1.0;
-    let final_light_intensity = light_angle * directional_light;
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        // Los cráteres se hunden de verdad: la pared que mira al sol se ilumina y la otra no
+        let normal = fragment.bump_normal(1.0, |point| self.height(uniforms, point));
+        blinn_phong(fragment, &normal, uniforms.lights, &Specular::matte()).apply(surface.albedo, surface.specular_mask)
+    }
+}
 
-    let base_color = if water_depth_value > 0.5 {
-        shallow_water_color.lerp(&deep_water_color, (water_depth_value - 0.5) * 2.0)
-    } else {
-        deep_water_color.lerp(&ocean_floor_color, water_depth_value * 2.0)
-    };
 
-    let mut final_color = base_color * final_light_intensity;
+pub struct SunMaterial {
+    // Centro casi blanco y borde anaranjado
+    pub core_color: Color,
+    pub surface_color: Color,
+    pub limb_color: Color,
+    pub granule_scale: f32,
+    pub activity_scale: f32,
+    // Coeficiente u de la ley lineal de oscurecimiento hacia el borde
+    pub limb_darkening: f32,
+    pub emission: f32,
+}
 
-    let shadow_noise = uniforms.noise.get_noise_3d(
-        position.x * 2500.0,
-        position.y * 2500.0,
-        position.z * 2500.0,
-    ) * 0.25;
-    let highlight_noise = uniforms.noise.get_noise_3d(
-        position.x * 3500.0,
-        position.y * 3500.0,
-        position.z * 3500.0,
-    ) * 0.15;
+impl Default for SunMaterial {
+    fn default() -> Self {
+        SunMaterial {
+            core_color: Color::new(255, 244, 214),
+            surface_color: Color::new(255, 196, 84),
+            limb_color: Color::new(214, 96, 24),
+            granule_scale: 1400.0,
+            activity_scale: 250.0,
+            limb_darkening: 0.6,
+            emission: 1.6,
+        }
+    }
+}
 
-    final_color = final_color * (1.0 + shadow_noise + highlight_noise);
+impl Material for SunMaterial {
+    // Todo cambia con el tiempo y con la vista: se calcula al iluminar
+    fn surface(&self, _fragment: &Fragment, _uniforms: &Uniforms) -> Surface {
+        Surface::new(self.surface_color).with_specular_mask(0.0)
+    }
 
-    let extra_depth_variation = uniforms.noise.get_noise_3d(
-        position.x * 5000.0,
-        position.y * 5000.0,
-        position.z * 5000.0,
-    ) * 0.1;
-    final_color = final_color * (1.0 + extra_depth_variation);
+    fn light(&self, _surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        // Posición estable sobre la esfera para que la granulación no dependa de la cámara
+        let position = fragment.vertex_position.normalize();
+        let time = uniforms.time;
+
+        // Granulación: celdas de convección que hierven con el tiempo
+        let granules = uniforms.noise.get_noise_3d(
+            position.x * self.granule_scale + time * 25.0,
+            position.y * self.granule_scale,
+            position.z * self.granule_scale - time * 15.0,
+        );
+        let granulation = 1.0 - granules.abs();
+
+        // Manchas y regiones activas a mayor escala
+        let activity = uniforms.noise.get_noise_3d(
+            position.x * self.activity_scale,
+            position.y * self.activity_scale + time * 2.0,
+            position.z * self.activity_scale,
+        );
+
+        let base_color = self.surface_color.lerp(&self.core_color, granulation * 0.8 + activity * 0.2);
+
+        let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+        let mu = fragment.normal.dot(&view_dir).clamp(0.0, 1.0);
+        let limb_darkening = 1.0 - self.limb_darkening * (1.0 - mu);
+
+        // Emisivo: no usa la intensidad de la iluminación y brilla por encima de 1.0
+        self.limb_color.lerp(&base_color, mu.sqrt()) * (limb_darkening * self.emission)
+    }
 
-    final_color * fragment.intensity
+    fn bakeable(&self) -> bool {
+        false
+    }
 }
 
 
+// Casco metálico con paneles y franjas de color
+pub struct HullMaterial {
+    pub hull_color: Color,
+    pub panel_color: Color,
+    pub stripe_color: Color,
+    pub panel_scale: f32,
+    pub stripe_frequency: f32,
+    pub specular: Specular,
+    // Juntas y remaches en espacio tangente, sobre las coordenadas de textura del modelo
+    pub normal_map: Option<Arc<NormalMap>>,
+    pub normal_strength: f32,
+}
 
-pub fn moon_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let color1 = Color::new(247, 225, 195);
-    let color2 = Color::new(248, 228, 201);
-    let color3 = Color::new(249, 231, 207);
-    let color4 = Color::new(249, 234, 213);
-    let color5 = Color::new(250, 237, 219);
+impl Default for HullMaterial {
+    fn default() -> Self {
+        HullMaterial {
+            hull_color: Color::new(168, 176, 188),
+            panel_color: Color::new(120, 128, 142),
+            stripe_color: Color::new(210, 80, 40),
+            panel_scale: 1500.0,
+            stripe_frequency: 40.0,
+            specular: HULL_SPECULAR,
+            normal_map: None,
+            normal_strength: 1.0,
+        }
+    }
+}
 
-    let position = Vec3::new(
-        fragment.vertex_position.x,
-        fragment.vertex_position.y,
-        fragment.depth,
-    );
-    let noise_scale = 600.0;
-    let crater_scale = 150.0;
-    let fine_scale = 1200.0;
-
-    let base_noise = uniforms.noise.get_noise_3d(
-        position.x * noise_scale,
-        position.y * noise_scale,
-        position.z * noise_scale,
-    );
+impl Material for HullMaterial {
+    fn surface(&self, fragment: &Fragment, uniforms: &Uniforms) -> Surface {
+        let position = fragment.vertex_position;
 
-    let crater_noise = uniforms.noise.get_noise_3d(
-        position.x * crater_scale,
-        position.y * crater_scale,
-        position.z * crater_scale,
-    );
+        // Paneles del casco: ruido de baja frecuencia fijo al modelo
+        let panel_noise = sample(uniforms, &position, self.panel_scale);
 
-    let is_crater = crater_noise < -0.1;
-
-    let fine_detail = uniforms.noise.get_noise_3d(
-        position.x * fine_scale,
-        position.y * fine_scale,
-        position.z * fine_scale,
-    ) * 0.3;
-
-    let combined_value = (base_noise + fine_detail).clamp(1.0, 2.0);
-
-    let base_color = if is_crater {
-        color3
-    } else {
-        let color_choice = if combined_value < 0.2 {
-            color1
-        } else if combined_value < 0.4 {
-            color2
-        } else if combined_value < 0.6 {
-            color3
-        } else if combined_value < 0.8 {
-            color4
+        let base_color = if (position.z * self.stripe_frequency).sin().abs() < 0.1 {
+            self.stripe_color
         } else {
-            color5
+            self.hull_color.lerp(&self.panel_color, (panel_noise * 0.5 + 0.5) * 0.6)
         };
-        color_choice * combined_value
-    };
 
-    let light_angle = (position.y * 0.3 + uniforms.time as f32 * 0.002).sin() * 0.5 + 0.5;
-    let directional_light = (position.x * 0.1 + uniforms.time as f32 * 0.003).cos() * 0.2 + 0.8;
-    let final_light_intensity = light_angle * directional_light;
+        // Los paneles más gastados reflejan menos
+        Surface::new(base_color).with_specular_mask(0.6 + panel_noise * 0.4)
+    }
 
-    let mut final_color = base_color * final_light_intensity;
+    fn light(&self, surface: &Surface, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let normal = match &self.normal_map {
+            Some(map) => {
+                let detail = map.sample(&fragment.tex_coords);
+                fragment.perturb_normal(&Vec3::new(detail.x * self.normal_strength, detail.y * self.normal_strength, detail.z))
+            }
+            None => fragment.normal,
+        };
+        blinn_phong(fragment, &normal, uniforms.lights, &self.specular).apply(surface.albedo, surface.specular_mask)
+    }
+
+    // La nave no es una esfera
+    fn bakeable(&self) -> bool {
+        false
+    }
+}
 
-    let shadow_noise = uniforms.noise.get_noise_3d(
-        position.x * 2000.0,
-        position.y * 2000.0,
-        position.z * 2000.0,
-    ) * 0.1;
-    let highlight_noise = uniforms.noise.get_noise_3d(
-        position.x * 2500.0,
-        position.y * 2500.0,
-        position.z * 2500.0,
-    ) * 0.05;
 
-    final_color = final_color * (1.0 + shadow_noise + highlight_noise);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{look_at, perspective, Mat4};
+    use crate::noise::Noise;
+    use crate::light::Light;
+    use crate::material::MaterialRegistry;
+
+    fn uniforms_from<'a>(eye: Vec3, lights: &'a [Light], noise: &'a Noise) -> Uniforms<'a> {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: look_at(&eye, &Vec3::zeros(), &Vec3::y()),
+            projection_matrix: perspective(4.0 / 3.0, 0.8, 0.1, 1000.0),
+            viewport_matrix: Mat4::identity(),
+            time: 2.5,
+            noise,
+            lights,
+            camera_position: eye,
+            shadow_map: None,
+        }
+    }
 
-    final_color * fragment.intensity
+    // El mismo punto de la superficie visto desde dos cámaras: cambia el pixel y
+    // la profundidad, pero el patrón tiene que ser idéntico
+    #[test]
+    fn surface_patterns_do_not_depend_on_the_camera() {
+        // Solo luz ambiental, así no entra el brillo especular (que sí depende de la vista)
+        let lights = [Light::ambient(Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let noise = Noise::default();
+        let near = uniforms_from(Vec3::new(0.0, 0.5, 3.0), &lights, &noise);
+        let far = uniforms_from(Vec3::new(-12.0, 4.0, -20.0), &lights, &noise);
+
+        let materials = MaterialRegistry::with_defaults();
+        let names = ["rocky", "volcanic", "icy", "desert", "water", "moon", "gas_giant", "gas_giant2", "ring"];
+
+        for point in [Vec3::new(0.3, 0.2, 0.35), Vec3::new(-0.1, -0.45, 0.2), Vec3::new(0.05, 0.1, -0.49)] {
+            let normal = point.normalize();
+            let seen_near = Fragment::new(412.0, 280.0, 0.91, normal, 1.0, point, point);
+            let seen_far = Fragment::new(37.0, 501.0, 0.998, normal, 1.0, point, point);
+
+            for name in names {
+                let material = materials.get(name).unwrap();
+                let a = material.shade(&seen_near, &near);
+                let b = material.shade(&seen_far, &far);
+                assert_eq!((a.to_vec3(), a.alpha()), (b.to_vec3(), b.alpha()), "{} cambia con la cámara en {:?}", name, point);
+            }
+        }
+    }
+
+    #[test]
+    fn gas_giant_bands_follow_the_spin_axis() {
+        let lights = [Light::ambient(Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let noise = Noise::default();
+        let uniforms = uniforms_from(Vec3::new(0.0, 0.5, 3.0), &lights, &noise);
+        let calm = GasGiantMaterial { turbulence: 0.0, streak_scale: 0.0, storms: Vec::new(), ..GasGiantMaterial::default() };
+        let color_at = |latitude: f32, longitude: f32| {
+            let point = sphere_point(latitude, longitude) * 0.5;
+            let fragment = Fragment::new(0.0, 0.0, 0.0, point.normalize(), 1.0, point, point);
+            calm.surface(&fragment, &uniforms).albedo.to_vec3()
+        };
+
+        // Sin remolinos el color solo cambia con la latitud del modelo
+        for latitude in [-0.9, -0.2, 0.4] {
+            let reference = color_at(latitude, 0.0);
+            for longitude in [1.0, 2.5, -2.0] {
+                assert!((color_at(latitude, longitude) - reference).magnitude() < 1e-4);
+            }
+        }
+        assert!((color_at(0.1, 0.0) - color_at(0.3, 0.0)).magnitude() > 1e-2);
+    }
+
+    #[test]
+    fn storms_drift_with_the_wind_of_their_latitude() {
+        let lights = [Light::ambient(Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let noise = Noise::default();
+        let mut uniforms = uniforms_from(Vec3::new(0.0, 0.5, 3.0), &lights, &noise);
+        uniforms.time = 40.0;
+        let giant = GasGiantMaterial::default();
+        let storm = giant.storms[0];
+
+        let longitude = storm.longitude + giant.wind(storm.latitude) * uniforms.time;
+        let point = sphere_point(storm.latitude, longitude) * 0.5;
+        let fragment = Fragment::new(0.0, 0.0, 0.0, point.normalize(), 1.0, point, point);
+        let color = giant.surface(&fragment, &uniforms).albedo.to_vec3();
+        assert!((color - Color::from_srgb_vec3(storm.color).to_vec3()).magnitude() < 1e-3, "{:?}", color);
+    }
+
+    #[test]
+    fn overlapping_storms_do_not_depend_on_their_order() {
+        let lights = [Light::ambient(Vec3::new(1.0, 1.0, 1.0), 1.0)];
+        let noise = Noise::default();
+        let uniforms = uniforms_from(Vec3::new(0.0, 0.5, 3.0), &lights, &noise);
+        let west = Storm { latitude: 0.3, longitude: 1.0, radius: 0.1, elongation: 1.5, color: Vec3::new(0.8, 0.3, 0.2), spin: 3.0 };
+        let east = Storm { longitude: 1.2, color: Vec3::new(0.9, 0.9, 0.8), spin: -2.0, ..west };
+        let giant = |storms: Vec<Storm>| GasGiantMaterial { storms, ..GasGiantMaterial::default() };
+        let (forward, backward) = (giant(vec![west, east]), giant(vec![east, west]));
+
+        // Puntos entre los dos óvalos, donde ambos retuercen las bandas
+        for longitude in [1.05, 1.1, 1.15] {
+            let point = sphere_point(0.32, longitude) * 0.5;
+            let fragment = Fragment::new(0.0, 0.0, 0.0, point.normalize(), 1.0, point, point);
+            let a = forward.surface(&fragment, &uniforms).albedo.to_vec3();
+            let b = backward.surface(&fragment, &uniforms).albedo.to_vec3();
+            assert!((a - b).magnitude() < 1e-5, "{:?} != {:?} en {}", a, b, longitude);
+        }
+    }
 }
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use nalgebra_glm::{Vec3, Vec4, Mat4, look_at, perspective};
+use crate::fragment::Fragment;
+use crate::framebuffer::Framebuffer;
+use crate::triangle::triangle;
+use crate::utils::Frustum;
+use crate::vertex::Vertex;
+
+// Por debajo de esta cobertura un texel del objeto deja pasar la luz
+const MIN_COVERAGE: f32 = 0.5;
+
+// Mapa de sombras visto desde la luz, encuadrado sobre un receptor a la vez.
+// Guarda la distancia lineal a la luz en un framebuffer de solo profundidad.
+pub struct ShadowMap {
+    pub depth: Framebuffer,
+    view_matrix: Mat4,
+    projection_matrix: Mat4,
+    viewport_matrix: Mat4,
+    bias: f32,
+}
+
+impl ShadowMap {
+    pub fn new(size: usize) -> Self {
+        let half = size as f32 / 2.0;
+        ShadowMap {
+            depth: Framebuffer::depth_only(size, size),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::new(
+                half, 0.0, 0.0, half,
+                0.0, -half, 0.0, half,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ),
+            bias: 0.0,
+        }
+    }
+
+    // Apunta la cámara de la luz a la esfera del receptor y limpia el mapa
+    #[cfg(test)]
+    pub fn focus(&mut self, light_position: &Vec3, target: &Vec3, radius: f32) {
+        self.aim(light_position, target, radius);
+        self.depth.clear();
+    }
+
+    // Solo mueve la cámara de la luz, sin tocar lo que ya está dibujado
+    fn aim(&mut self, light_position: &Vec3, target: &Vec3, radius: f32) {
+        let to_target = target - light_position;
+        let distance = to_target.magnitude().max(radius * 1.01);
+        let direction = to_target / distance;
+
+        let up = if direction.y.abs() > 0.99 { Vec3::z() } else { Vec3::y() };
+        let half_angle = (radius / distance).min(0.99).asin() * 1.1;
+
+        self.view_matrix = look_at(light_position, target, &up);
+        self.projection_matrix = perspective(1.0, half_angle * 2.0, 0.05, distance + radius);
+        self.bias = radius * 0.04;
+    }
+
+    // Solo los objetos dentro del cono de la luz pueden sombrear al receptor
+    pub fn frustum(&self) -> Frustum {
+        Frustum::new(&self.view_matrix, &self.projection_matrix)
+    }
+
+    fn to_light_space(&self, world_position: &Vec3) -> Option<Vec3> {
+        let view_position = self.view_matrix * Vec4::new(world_position.x, world_position.y, world_position.z, 1.0);
+        let clip = self.projection_matrix * view_position;
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+        let screen = self.viewport_matrix * Vec4::new(clip.x / clip.w, clip.y / clip.w, 0.0, 1.0);
+        Some(Vec3::new(screen.x, screen.y, -view_position.z))
+    }
+
+    // Rasteriza un objeto que proyecta sombra en el mapa; `coverage` da la opacidad
+    // de cada fragmento para que los huecos de un objeto transparente dejen pasar la luz
+    pub fn render_caster(&mut self, vertices: &[Vertex], model_matrix: &Mat4, coverage: impl Fn(&Fragment) -> f32) {
+        let clip = self.depth.clip_rect();
+        let transformed = vertices.iter()
+            .map(|vertex| {
+                let world = model_matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+                let mut light_vertex = vertex.clone();
+                light_vertex.transformed_position = self.to_light_space(&world.xyz())?;
+                Some(light_vertex)
+            })
+            .collect::<Vec<_>>();
+
+        for tri in transformed.chunks(3) {
+            if let [Some(a), Some(b), Some(c)] = tri {
+                for fragment in triangle(a, b, c, &clip) {
+                    if coverage(&fragment) < MIN_COVERAGE {
+                        continue;
+                    }
+                    self.depth.depth_point(fragment.position.x as usize, fragment.position.y as usize, fragment.depth);
+                }
+            }
+        }
+    }
+
+    // Fracción de luz que llega al punto (1 = iluminado), filtrada con PCF 3x3
+    pub fn visibility(&self, world_position: &Vec3) -> f32 {
+        let light_space = match self.to_light_space(world_position) {
+            Some(point) => point,
+            None => return 1.0,
+        };
+
+        let (x, y) = (light_space.x.floor() as i32, light_space.y.floor() as i32);
+        let mut lit = 0.0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (sx, sy) = (x + dx, y + dy);
+                let occluder = if sx >= 0 && sy >= 0 && (sx as usize) < self.depth.width && (sy as usize) < self.depth.height {
+                    self.depth.zbuffer[sy as usize * self.depth.width + sx as usize]
+                } else {
+                    f32::INFINITY
+                };
+                if light_space.z - self.bias <= occluder {
+                    lit += 1.0;
+                }
+            }
+        }
+
+        lit / 9.0
+    }
+}
+
+
+// Dónde estaban la luz, el receptor y los cuerpos dibujados la última vez que se llenó un mapa
+#[derive(PartialEq)]
+struct Snapshot {
+    light_position: Vec3,
+    target: Vec3,
+    casters: Vec<(usize, Vec3)>,
+}
+
+// Un mapa de sombras por cada par (luz, receptor). Un mapa solo se vuelve a dibujar
+// si se movió la luz, el receptor o alguno de los cuerpos que caen en el cono de la luz.
+// El giro de un cuerpo sobre su eje no cuenta: la silueta de una esfera no cambia.
+pub struct ShadowCache {
+    size: usize,
+    maps: HashMap<(usize, usize), (ShadowMap, Option<Snapshot>)>,
+}
+
+impl ShadowCache {
+    pub fn new(size: usize) -> Self {
+        ShadowCache { size, maps: HashMap::new() }
+    }
+
+    // Mapa del par `(luz, receptor)` encuadrado sobre la esfera del receptor. `casters` trae
+    // el índice, el centro y el radio de cada cuerpo que puede dar sombra; `draw` recibe el
+    // mapa limpio y los índices de los que entran en el cono, y solo se llama si algo se movió
+    pub fn map(
+        &mut self,
+        key: (usize, usize),
+        light_position: &Vec3,
+        target: &Vec3,
+        radius: f32,
+        casters: &[(usize, Vec3, f32)],
+        draw: impl FnOnce(&mut ShadowMap, &[usize]),
+    ) -> &ShadowMap {
+        let size = self.size;
+        let (shadow_map, snapshot) = self.maps.entry(key).or_insert_with(|| (ShadowMap::new(size), None));
+        shadow_map.aim(light_position, target, radius);
+
+        let frustum = shadow_map.frustum();
+        let casters = casters.iter()
+            .filter(|(_, center, radius)| frustum.intersects_sphere(center, *radius))
+            .map(|(index, center, _)| (*index, *center))
+            .collect::<Vec<_>>();
+        let current = Snapshot { light_position: *light_position, target: *target, casters };
+
+        if snapshot.as_ref() != Some(&current) {
+            shadow_map.depth.clear();
+            let indices = current.casters.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+            draw(shadow_map, &indices);
+            *snapshot = Some(current);
+        }
+        shadow_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+
+    // Cuadrado de lado 2 en el plano XY, entre la luz y el origen
+    fn quad() -> Vec<Vertex> {
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|(x, y)| Vertex::new(Vec3::new(*x, *y, 0.0), Vec3::z(), Vec2::zeros()))
+            .collect()
+    }
+
+    #[test]
+    fn transparent_parts_of_a_caster_let_light_through() {
+        let mut shadow_map = ShadowMap::new(64);
+        let light = Vec3::new(0.0, 0.0, 10.0);
+        shadow_map.focus(&light, &Vec3::new(0.0, 0.0, -2.0), 1.5);
+
+        // La mitad izquierda del cuadrado es un hueco
+        let model = Mat4::identity();
+        shadow_map.render_caster(&quad(), &model, |fragment| if fragment.vertex_position.x < 0.0 { 0.1 } else { 1.0 });
+
+        assert_eq!(shadow_map.visibility(&Vec3::new(-0.5, 0.0, -2.0)), 1.0);
+        assert_eq!(shadow_map.visibility(&Vec3::new(0.5, 0.0, -2.0)), 0.0);
+    }
+
+    #[test]
+    fn cached_maps_are_redrawn_only_when_something_moves() {
+        let mut cache = ShadowCache::new(64);
+        let light = Vec3::new(0.0, 0.0, 10.0);
+        let target = Vec3::new(0.0, 0.0, -2.0);
+        let mut draws = 0;
+        let mut frame = |cache: &mut ShadowCache, caster: Vec3| {
+            cache.map((0, 1), &light, &target, 1.5, &[(2, caster, 1.0)], |_, indices| {
+                assert_eq!(indices, &[2]);
+                draws += 1;
+            });
+        };
+
+        frame(&mut cache, Vec3::zeros());
+        frame(&mut cache, Vec3::zeros());
+        frame(&mut cache, Vec3::new(0.2, 0.0, 0.0));
+        assert_eq!(draws, 2);
+    }
+}
@@ -0,0 +1,300 @@
+use nalgebra_glm::{Vec3, Vec4, Mat4};
+use fastnoise_lite::FractalType;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::noise::{Fractal, Noise, NoiseConfig};
+use crate::utils::{cube_face_direction, cube_locate, unproject};
+
+// Parámetros del campo de estrellas
+pub struct StarfieldConfig {
+    pub seed: u64,
+    // Número de estrellas en todo el cielo
+    pub count: usize,
+    // Magnitud de la estrella más brillante y de la más débil
+    pub magnitude_range: (f32, f32),
+    // Pendiente de log10 N(<m): con 0.6 cada magnitud hay unas 4 veces más estrellas
+    pub magnitude_slope: f32,
+    // Temperaturas de color en kelvin; las frías son más frecuentes
+    pub temperature_range: (f32, f32),
+    // Radiancia de una estrella de magnitud 0
+    pub brightness: f32,
+}
+
+impl Default for StarfieldConfig {
+    fn default() -> Self {
+        StarfieldConfig {
+            seed: 7,
+            count: 6000,
+            magnitude_range: (-1.0, 7.0),
+            magnitude_slope: 0.6,
+            temperature_range: (2800.0, 15000.0),
+            brightness: 6.0,
+        }
+    }
+}
+
+// Nubes de gas de ruido fractal alrededor de todo el cielo
+pub struct Nebula {
+    pub seed: i32,
+    pub scale: f32,
+    // Colores lineales de las zonas densas y de los bordes
+    pub core_color: Color,
+    pub edge_color: Color,
+    pub intensity: f32,
+    // Densidad mínima de ruido para que haya gas
+    pub threshold: f32,
+}
+
+impl Default for Nebula {
+    fn default() -> Self {
+        Nebula {
+            seed: 91,
+            scale: 180.0,
+            core_color: Color::linear(0.55, 0.12, 0.45),
+            edge_color: Color::linear(0.05, 0.18, 0.5),
+            intensity: 0.4,
+            threshold: -0.2,
+        }
+    }
+}
+
+struct Star {
+    direction: Vec3,
+    radiance: Vec3,
+}
+
+// Fondo a distancia infinita: gira con la cámara pero no se traslada
+pub struct Skybox {
+    stars: Vec<Star>,
+    nebula: Option<(Nebula, Noise)>,
+    cubemap: Option<Cubemap>,
+}
+
+impl Skybox {
+    pub fn new(config: &StarfieldConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let (min_mag, max_mag) = config.magnitude_range;
+        let (min_temp, max_temp) = config.temperature_range;
+        let slope = config.magnitude_slope;
+
+        let stars = (0..config.count)
+            .map(|_| {
+                // Dirección uniforme en la esfera
+                let z: f32 = rng.gen_range(-1.0..1.0);
+                let phi: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+                let r = (1.0 - z * z).sqrt();
+                let direction = Vec3::new(r * phi.cos(), z, r * phi.sin());
+
+                // Inversa de la distribución acumulada N(<m) ∝ 10^(slope·m)
+                let u: f32 = rng.gen();
+                let low = 10f32.powf(slope * min_mag);
+                let high = 10f32.powf(slope * max_mag);
+                let magnitude = (low + u * (high - low)).log10() / slope;
+                let flux = 10f32.powf(-0.4 * magnitude) * config.brightness;
+
+                let temperature = min_temp + (max_temp - min_temp) * rng.gen::<f32>().powi(2);
+                Star { direction, radiance: blackbody(temperature) * flux }
+            })
+            .collect();
+
+        Skybox { stars, nebula: None, cubemap: None }
+    }
+
+    pub fn with_nebula(mut self, nebula: Nebula) -> Self {
+        let noise = NoiseConfig::new(nebula.seed)
+            .with_fractal(FractalType::FBm, Fractal::new(0.01, 5, 2.0, 0.5))
+            .build();
+        self.nebula = Some((nebula, noise));
+        self.cubemap = None;
+        self
+    }
+
+    // Precalcula estrellas y nebulosa en un cubemap; cada cuadro solo lo muestrea
+    pub fn baked(mut self, face_size: usize) -> Self {
+        let mut cubemap = Cubemap::new(face_size);
+        for face in 0..6 {
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let direction = cubemap.texel_direction(face, x, y);
+                    cubemap.texels[(face * face_size + y) * face_size + x] = self.nebula_radiance(&direction);
+                }
+            }
+        }
+        for star in &self.stars {
+            cubemap.splat(&star.direction, star.radiance);
+        }
+        self.cubemap = Some(cubemap);
+        self
+    }
+
+    fn nebula_radiance(&self, direction: &Vec3) -> Vec3 {
+        let Some((nebula, noise)) = &self.nebula else {
+            return Vec3::zeros();
+        };
+        let p = direction * nebula.scale;
+        let density = noise.get_noise_3d(p.x, p.y, p.z);
+        if density <= nebula.threshold {
+            return Vec3::zeros();
+        }
+        let density = ((density - nebula.threshold) / (1.0 - nebula.threshold)).powf(1.5);
+
+        // Segundo ruido desplazado para mezclar los dos colores
+        let tint = noise.get_noise_3d(p.z + 500.0, p.x, p.y) * 0.5 + 0.5;
+        let color = nebula.edge_color.lerp(&nebula.core_color, tint).to_vec3();
+        color * (density * nebula.intensity)
+    }
+
+    // Dibuja el fondo en HDR antes de la geometría, sin escribir profundidad
+    pub fn draw(&self, framebuffer: &mut Framebuffer, view_matrix: &Mat4, projection_matrix: &Mat4) {
+        // Solo la rotación de la vista: el cielo está infinitamente lejos
+        let mut rotation = *view_matrix;
+        rotation.set_column(3, &Vec4::new(0.0, 0.0, 0.0, 1.0));
+        let view_projection = projection_matrix * rotation;
+        let Some(inverse) = view_projection.try_inverse() else {
+            return;
+        };
+        let (width, height) = (framebuffer.width, framebuffer.height);
+
+        if let Some(cubemap) = &self.cubemap {
+            for y in 0..height {
+                for x in 0..width {
+                    let direction = pixel_direction(&inverse, x, y, width, height);
+                    framebuffer.color_buffer[y * width + x] += cubemap.sample(&direction);
+                }
+            }
+            return;
+        }
+
+        if self.nebula.is_some() {
+            for y in 0..height {
+                for x in 0..width {
+                    let direction = pixel_direction(&inverse, x, y, width, height);
+                    framebuffer.color_buffer[y * width + x] += self.nebula_radiance(&direction);
+                }
+            }
+        }
+
+        // Cada estrella se reparte entre los cuatro pixeles más cercanos
+        for star in &self.stars {
+            let clip = view_projection * Vec4::new(star.direction.x, star.direction.y, star.direction.z, 1.0);
+            if clip.w <= 0.0 {
+                continue;
+            }
+            let sx = (clip.x / clip.w + 1.0) * 0.5 * width as f32 - 0.5;
+            let sy = (1.0 - clip.y / clip.w) * 0.5 * height as f32 - 0.5;
+            if sx < 0.0 || sy < 0.0 || sx >= (width - 1) as f32 || sy >= (height - 1) as f32 {
+                continue;
+            }
+            let (x0, y0) = (sx as usize, sy as usize);
+            let (tx, ty) = (sx - x0 as f32, sy - y0 as f32);
+            for (dx, dy, weight) in [(0, 0, (1.0 - tx) * (1.0 - ty)), (1, 0, tx * (1.0 - ty)), (0, 1, (1.0 - tx) * ty), (1, 1, tx * ty)] {
+                framebuffer.color_buffer[(y0 + dy) * width + x0 + dx] += star.radiance * weight;
+            }
+        }
+    }
+}
+
+// Dirección de mundo que pasa por el centro del pixel
+fn pixel_direction(inverse_view_projection: &Mat4, x: usize, y: usize, width: usize, height: usize) -> Vec3 {
+    unproject(inverse_view_projection, x, y, width, height, 1.0).normalize()
+}
+
+// Color aproximado de un cuerpo negro (ajuste de Tanner Helland), en lineal
+// con el canal mayor en 1
+fn blackbody(temperature: f32) -> Vec3 {
+    let t = temperature / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.699 * (t - 60.0).powf(-0.133_204_76) };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+
+    let srgb = Vec3::new(red, green, blue).map(|c| c.clamp(0.0, 255.0) / 255.0);
+    let linear = Color::from_srgb_vec3(srgb).to_vec3();
+    linear / linear.max()
+}
+
+// Seis caras cuadradas en el orden +X, -X, +Y, -Y, +Z, -Z
+struct Cubemap {
+    size: usize,
+    texels: Vec<Vec3>,
+}
+
+impl Cubemap {
+    fn new(size: usize) -> Self {
+        Cubemap { size, texels: vec![Vec3::zeros(); size * size * 6] }
+    }
+
+    fn texel_direction(&self, face: usize, x: usize, y: usize) -> Vec3 {
+        let u = (x as f32 + 0.5) / self.size as f32 * 2.0 - 1.0;
+        let v = (y as f32 + 0.5) / self.size as f32 * 2.0 - 1.0;
+        cube_face_direction(face, u, v).normalize()
+    }
+
+    // Posición continua del texel, con los centros en enteros
+    fn texel_coords(&self, u: f32, v: f32) -> (f32, f32) {
+        let size = self.size as f32;
+        ((u + 1.0) * 0.5 * size - 0.5, (v + 1.0) * 0.5 * size - 0.5)
+    }
+
+    fn sample(&self, direction: &Vec3) -> Vec3 {
+        let (face, u, v) = cube_locate(direction);
+        let (fx, fy) = self.texel_coords(u, v);
+        let max = (self.size - 1) as f32;
+        let (fx, fy) = (fx.clamp(0.0, max), fy.clamp(0.0, max));
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+        let texel = |x: usize, y: usize| self.texels[(face * self.size + y) * self.size + x];
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x1, y0) * tx;
+        let bottom = texel(x0, y1) * (1.0 - tx) + texel(x1, y1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    fn splat(&mut self, direction: &Vec3, radiance: Vec3) {
+        let (face, u, v) = cube_locate(direction);
+        let (fx, fy) = self.texel_coords(u, v);
+        let max = (self.size - 1) as f32;
+        let (x, y) = (fx.round().clamp(0.0, max) as usize, fy.round().clamp(0.0, max) as usize);
+        self.texels[(face * self.size + y) * self.size + x] += radiance;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cubemap_locate_inverts_texel_direction() {
+        let cubemap = Cubemap::new(8);
+        for face in 0..6 {
+            for (x, y) in [(0, 0), (3, 5), (7, 7)] {
+                let direction = cubemap.texel_direction(face, x, y);
+                let (found, u, v) = cube_locate(&direction);
+                let (fx, fy) = cubemap.texel_coords(u, v);
+                assert_eq!(found, face);
+                assert!((fx - x as f32).abs() < 1e-3 && (fy - y as f32).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn blackbody_goes_from_red_to_blue() {
+        let cool = blackbody(3000.0);
+        let hot = blackbody(12000.0);
+        assert!(cool.x > cool.z);
+        assert!(hot.z > hot.x);
+    }
+}
@@ -1,7 +1,6 @@
+use std::sync::Arc;
 use nalgebra_glm::Vec3;
-use crate::fragment::Fragment;
-use crate::Uniforms;
-use crate::color::Color;
+use crate::material::Material;
 use crate::vertex::Vertex;
 
 pub struct Spaceship {
@@ -9,7 +8,7 @@ pub struct Spaceship {
     pub rotation: Vec3,
     pub scale: f32,
     pub vertices: Vec<Vertex>,
-    pub shader: fn(&Fragment, &Uniforms) -> Color,
+    pub material: Arc<dyn Material>,
 }
 
 impl Spaceship {
@@ -18,14 +17,14 @@ impl Spaceship {
         rotation: Vec3,
         scale: f32,
         vertices: Vec<Vertex>,
-        shader: fn(&Fragment, &Uniforms) -> Color,
+        material: Arc<dyn Material>,
     ) -> Self {
         Spaceship {
             position,
             rotation,
             scale,
             vertices,
-            shader,
+            material,
         }
     }
 
@@ -0,0 +1,84 @@
+use nalgebra_glm::{Vec3, Vec4};
+use crate::framebuffer::{BlendMode, Framebuffer};
+use crate::color::Color;
+use crate::Uniforms;
+
+// Halo luminoso alrededor de un cuerpo emisivo, dibujado en espacio de pantalla
+pub struct Corona {
+    pub color: Color,
+    // Alcance del halo medido en radios del cuerpo
+    pub extent: f32,
+    pub intensity: f32,
+}
+
+// Color lineal con brillo HDR, más intenso que el blanco de pantalla cerca del disco
+pub const SUN_CORONA: Corona = Corona {
+    color: Color::linear(1.0, 0.4, 0.06),
+    extent: 2.5,
+    intensity: 1.2,
+};
+
+// Proyecta un punto del mundo a pantalla; None si está detrás de la cámara
+pub fn project(point: &Vec3, uniforms: &Uniforms) -> Option<Vec3> {
+    let clip = uniforms.projection_matrix * uniforms.view_matrix * Vec4::new(point.x, point.y, point.z, 1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+    let ndc = Vec4::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w, 1.0);
+    let screen = uniforms.viewport_matrix * ndc;
+    Some(Vec3::new(screen.x, screen.y, screen.z))
+}
+
+// Suma el halo sobre los pixeles que no tienen nada delante del cuerpo
+pub fn draw_corona(framebuffer: &mut Framebuffer, corona: &Corona, center: &Vec3, radius: f32, uniforms: &Uniforms) {
+    let center_screen = match project(center, uniforms) {
+        Some(point) => point,
+        None => return,
+    };
+
+    // Radio en pixeles usando un punto desplazado sobre el eje derecho de la cámara
+    let view = &uniforms.view_matrix;
+    let right = Vec3::new(view[(0, 0)], view[(0, 1)], view[(0, 2)]);
+    let edge_screen = match project(&(center + right * radius), uniforms) {
+        Some(point) => point,
+        None => return,
+    };
+    let radius_px = (edge_screen.xy() - center_screen.xy()).magnitude();
+    if radius_px < 0.5 {
+        return;
+    }
+
+    // Suma aditiva sin escribir profundidad, detrás de todo lo que esté delante del cuerpo
+    framebuffer.set_blend_mode(BlendMode::Additive);
+    framebuffer.set_depth_write(false);
+
+    let reach = radius_px * corona.extent;
+    let clip = framebuffer.clip_rect();
+    let min_x = (center_screen.x - reach).floor().max(clip.x as f32) as usize;
+    let min_y = (center_screen.y - reach).floor().max(clip.y as f32) as usize;
+    let max_x = (center_screen.x + reach).ceil().min((clip.x + clip.width) as f32) as usize;
+    let max_y = (center_screen.y + reach).ceil().min((clip.y + clip.height) as f32) as usize;
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let offset = Vec3::new(x as f32 + 0.5 - center_screen.x, y as f32 + 0.5 - center_screen.y, 0.0);
+            let distance = offset.magnitude() / radius_px;
+            // El halo empieza bajo el disco; la prueba de profundidad lo oculta detrás del cuerpo
+            if distance > corona.extent {
+                continue;
+            }
+
+            // Serpentinas que cambian lentamente con el ángulo y el tiempo
+            let angle = offset.y.atan2(offset.x);
+            let streamers = uniforms.noise.get_noise_2d(angle.cos() * 300.0 + uniforms.time * 10.0, angle.sin() * 300.0) * 0.35 + 1.0;
+
+            let falloff = (1.0 - (distance - 1.0).max(0.0) / (corona.extent - 1.0)).powi(3);
+            let glow = corona.intensity * falloff * streamers;
+            framebuffer.set_current_color(corona.color * glow);
+            framebuffer.point(x, y, center_screen.z);
+        }
+    }
+
+    framebuffer.set_blend_mode(BlendMode::Replace);
+    framebuffer.set_depth_write(true);
+}
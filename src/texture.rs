@@ -0,0 +1,66 @@
+use nalgebra_glm::{Vec2, Vec3};
+
+// Mapa de normales en espacio tangente. Los archivos siguen la convención de OpenGL
+// (verde hacia arriba en la imagen); como `Obj` invierte v al cargar, la bitangente
+// apunta hacia abajo en la imagen y el verde se invierte al leerlo
+pub struct NormalMap {
+    width: usize,
+    height: usize,
+    texels: Vec<Vec3>,
+}
+
+impl NormalMap {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| format!("{}: {}", path, e))?.to_rgb8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(format!("{}: imagen vacía", path));
+        }
+
+        let texels = image.pixels()
+            .map(|pixel| {
+                let [r, g, b] = pixel.0.map(|c| c as f32 / 255.0 * 2.0 - 1.0);
+                Vec3::new(r, -g, b)
+            })
+            .collect();
+        Ok(NormalMap { width, height, texels })
+    }
+
+    // Filtrado bilineal con repetición; devuelve la normal en espacio tangente, unitaria
+    pub fn sample(&self, tex_coords: &Vec2) -> Vec3 {
+        let fx = tex_coords.x * self.width as f32 - 0.5;
+        let fy = tex_coords.y * self.height as f32 - 0.5;
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x as isize).rem_euclid(self.width as isize) as usize;
+            let y = (y as isize).rem_euclid(self.height as isize) as usize;
+            self.texels[y * self.width + x]
+        };
+        let top = texel(x0, y0).lerp(&texel(x0 + 1.0, y0), tx);
+        let bottom = texel(x0, y0 + 1.0).lerp(&texel(x0 + 1.0, y0 + 1.0), tx);
+        let normal = top.lerp(&bottom, ty);
+        if normal.magnitude_squared() > f32::EPSILON { normal.normalize() } else { Vec3::z() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_wrap_and_stay_unit_length() {
+        // Un texel plano y uno inclinado hacia arriba en la imagen
+        let flat = Vec3::new(0.0, 0.0, 1.0);
+        let up = Vec3::new(0.0, -0.6, 0.8);
+        let map = NormalMap { width: 2, height: 1, texels: vec![flat, up] };
+
+        assert!((map.sample(&Vec2::new(0.25, 0.5)) - flat).magnitude() < 1e-5);
+        assert!((map.sample(&Vec2::new(0.75, 0.5)) - up).magnitude() < 1e-5);
+        // A medio camino se mezclan y se renormalizan; u = 1 vuelve al primer texel
+        assert!((map.sample(&Vec2::new(0.5, 0.5)).magnitude() - 1.0).abs() < 1e-5);
+        assert!((map.sample(&Vec2::new(1.25, 0.5)) - flat).magnitude() < 1e-5);
+    }
+}
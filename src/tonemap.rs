@@ -0,0 +1,75 @@
+use nalgebra_glm::Vec3;
+
+// Operador para comprimir el rango HDR a [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMapper {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapper::Clamp => ToneMapper::Reinhard,
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::Clamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    pub exposure: f32,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapper, exposure: f32) -> Self {
+        ToneMapping { operator, exposure }
+    }
+
+    // Color lineal HDR a lineal de pantalla en [0, 1]
+    pub fn map(&self, hdr: Vec3) -> Vec3 {
+        let exposed = hdr * self.exposure;
+        match self.operator {
+            ToneMapper::Clamp => exposed.map(|c| c.clamp(0.0, 1.0)),
+            ToneMapper::Reinhard => exposed.map(|c| c.max(0.0) / (1.0 + c.max(0.0))),
+            // Ajuste de la curva ACES de Narkowicz
+            ToneMapper::Aces => exposed.map(|c| {
+                let c = c.max(0.0);
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+
+    // Mapea, codifica en sRGB y empaqueta como 0xRRGGBB
+    pub fn encode(&self, hdr: Vec3) -> u32 {
+        let mapped = self.map(hdr);
+        let encode = |c: f32| (linear_to_srgb(c) * 255.0 + 0.5) as u32;
+        (encode(mapped.x) << 16) | (encode(mapped.y) << 8) | encode(mapped.z)
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping::new(ToneMapper::Aces, 1.0)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -1,15 +1,16 @@
-use nalgebra_glm::{Vec3, dot};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
 use crate::fragment::Fragment;
 use crate::vertex::Vertex;
-use crate::color::Color;
+use crate::framebuffer::Rect;
 
-pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Fragment> {
+pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, clip: &Rect) -> Vec<Fragment> {
   let mut fragments = Vec::new();
   let (a, b, c) = (v1.transformed_position, v2.transformed_position, v3.transformed_position);
 
-  let (min_x, min_y, max_x, max_y) = calculate_bounding_box(&a, &b, &c);
-
-  let light_dir = Vec3::new(0.0, 0.0, 1.0);
+  let (min_x, min_y, max_x, max_y) = match clip_bounding_box(calculate_bounding_box(&a, &b, &c), clip) {
+    Some(bounds) => bounds,
+    None => return fragments,
+  };
 
   let triangle_area = edge_function(&a, &b, &c);
 
@@ -19,33 +20,41 @@ pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Fragment> {
 
       let (w1, w2, w3) = barycentric_coordinates(&point, &a, &b, &c, triangle_area);
 
-      if w1 >= 0.0 && w1 <= 1.0 && 
-         w2 >= 0.0 && w2 <= 1.0 &&
-         w3 >= 0.0 && w3 <= 1.0 {
-
-        let normal = v1.transformed_normal * w1 + v2.transformed_normal * w2 + v3.transformed_normal * w3;
-        let normal = normal.normalize();
+      if (0.0..=1.0).contains(&w1) &&
+         (0.0..=1.0).contains(&w2) &&
+         (0.0..=1.0).contains(&w3) {
 
-        let intensity = dot(&normal, &light_dir).max(0.0);
+        // La profundidad de pantalla es lineal en pantalla; los atributos de la
+        // superficie se interpolan con corrección de perspectiva para que no se deslicen
+        let depth = a.z * w1 + b.z * w2 + c.z * w3;
+        let (p1, p2, p3) = perspective_weights(w1, w2, w3, v1, v2, v3);
 
-        let base_color = Color::new(100, 100, 100);
-        let lit_color = base_color * intensity;
+        // Normal en espacio de mundo; la iluminación se calcula después con las luces de la escena
+        let normal = v1.transformed_normal * p1 + v2.transformed_normal * p2 + v3.transformed_normal * p3;
+        let normal = normal.normalize();
 
-        let depth = a.z * w1 + b.z * w2 + c.z * w3;
+        let vertex_position = v1.position * p1 + v2.position * p2 + v3.position * p3;
+        let world_position = v1.world_position * p1 + v2.world_position * p2 + v3.world_position * p3;
 
-        let vertex_position = v1.position * w1 + v2.position * w2 + v3.position * w3;
-
-        fragments.push(
-            Fragment::new(
-                x as f32,
-                y as f32,
-                lit_color,
-                depth,
-                normal,
-                intensity,
-                vertex_position,
-            )
+        let mut fragment = Fragment::new(
+            x as f32,
+            y as f32,
+            depth,
+            normal,
+            1.0,
+            vertex_position,
+            world_position,
+        );
+        fragment.tex_coords = v1.tex_coords * p1 + v2.tex_coords * p2 + v3.tex_coords * p3;
+        fragment.set_tangent_frame(
+          v1.transformed_tangent * p1 + v2.transformed_tangent * p2 + v3.transformed_tangent * p3,
+          v1.transformed_bitangent * p1 + v2.transformed_bitangent * p2 + v3.transformed_bitangent * p3,
+          v1.normal * p1 + v2.normal * p2 + v3.normal * p3,
+          v1.tangent * p1 + v2.tangent * p2 + v3.tangent * p3,
+          v1.bitangent * p1 + v2.bitangent * p2 + v3.bitangent * p3,
         );
+
+        fragments.push(fragment);
       }
     }
   }
@@ -53,6 +62,45 @@ pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Fragment> {
   fragments
 }
 
+// Recorta un triángulo en espacio de objeto contra los planos cercano y lejano del
+// espacio de recorte (z = -w y z = w). Las distancias a los planos son afines en la
+// posición, así que cortar antes del vertex shader equivale a cortar en recorte.
+// Devuelve el abanico de triángulos que queda; vacío si el triángulo está fuera.
+pub fn clip_triangle(tri: [&Vertex; 3], clip_matrix: &Mat4) -> Vec<[Vertex; 3]> {
+  let distances = |vertex: &Vertex| {
+    let clip = clip_matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+    [clip.w + clip.z, clip.w - clip.z]
+  };
+
+  let inside = tri.map(|vertex| distances(vertex).iter().all(|d| *d >= 0.0));
+  if inside.iter().all(|inside| *inside) {
+    return vec![tri.map(Vertex::clone)];
+  }
+
+  let mut polygon = tri.map(Vertex::clone).to_vec();
+  for plane in 0..2 {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+      let next = &polygon[(i + 1) % polygon.len()];
+      let (d0, d1) = (distances(current)[plane], distances(next)[plane]);
+      if d0 >= 0.0 {
+        clipped.push(current.clone());
+      }
+      if (d0 >= 0.0) != (d1 >= 0.0) {
+        clipped.push(current.lerp(next, d0 / (d0 - d1)));
+      }
+    }
+    polygon = clipped;
+    if polygon.len() < 3 {
+      return Vec::new();
+    }
+  }
+
+  (1..polygon.len() - 1)
+    .map(|i| [polygon[0].clone(), polygon[i].clone(), polygon[i + 1].clone()])
+    .collect()
+}
+
 fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (i32, i32, i32, i32) {
     let min_x = v1.x.min(v2.x).min(v3.x).floor() as i32;
     let min_y = v1.y.min(v2.y).min(v3.y).floor() as i32;
@@ -62,6 +110,28 @@ fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (i32, i32, i32, i3
     (min_x, min_y, max_x, max_y)
 }
 
+// Intersecta la caja con la región visible; None si el triángulo queda fuera
+fn clip_bounding_box(bounds: (i32, i32, i32, i32), clip: &Rect) -> Option<(usize, usize, usize, usize)> {
+    if clip.is_empty() {
+        return None;
+    }
+
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let clip_max_x = (clip.x + clip.width - 1) as i32;
+    let clip_max_y = (clip.y + clip.height - 1) as i32;
+
+    let min_x = min_x.max(clip.x as i32);
+    let min_y = min_y.max(clip.y as i32);
+    let max_x = max_x.min(clip_max_x);
+    let max_y = max_y.min(clip_max_y);
+
+    if min_x > max_x || min_y > max_y {
+        return None;
+    }
+
+    Some((min_x as usize, min_y as usize, max_x as usize, max_y as usize))
+}
+
 fn barycentric_coordinates(p: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) -> (f32, f32, f32) {
     let w1 = edge_function(b, c, p) / area;
     let w2 = edge_function(c, a, p) / area;
@@ -70,8 +140,136 @@ fn barycentric_coordinates(p: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) ->
     (w1, w2, w3)
 }
 
+// Pesos baricéntricos de pantalla llevados a pesos sobre la superficie 3D
+fn perspective_weights(w1: f32, w2: f32, w3: f32, v1: &Vertex, v2: &Vertex, v3: &Vertex) -> (f32, f32, f32) {
+  let (p1, p2, p3) = (w1 * v1.inverse_w, w2 * v2.inverse_w, w3 * v3.inverse_w);
+  let total = p1 + p2 + p3;
+  if total.abs() <= f32::EPSILON {
+    return (w1, w2, w3);
+  }
+  (p1 / total, p2 / total, p3 / total)
+}
+
 fn edge_function(a: &Vec3, b: &Vec3, c: &Vec3) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
 
 
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use nalgebra_glm::{look_at, perspective, Mat4, Vec4};
+  use crate::noise::Noise;
+  use crate::shaders::vertex_shader;
+  use crate::Uniforms;
+
+  fn viewport(width: f32, height: f32) -> Mat4 {
+    Mat4::new(
+      width / 2.0, 0.0, 0.0, width / 2.0,
+      0.0, -height / 2.0, 0.0, height / 2.0,
+      0.0, 0.0, 1.0, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    )
+  }
+
+  // Rasteriza un triángulo inclinado que se aleja de la cámara y devuelve, por
+  // fragmento, la distancia en pixeles entre su centro y la reproyección de la
+  // posición de objeto interpolada
+  fn reprojection_errors(eye: Vec3) -> Vec<f32> {
+    let noise = Noise::default();
+    let model = Mat4::new_translation(&Vec3::new(0.0, 0.0, -2.0)) * Mat4::from_axis_angle(&Vec3::x_axis(), -1.2);
+    let uniforms = Uniforms {
+      model_matrix: model,
+      view_matrix: look_at(&eye, &Vec3::new(0.0, 0.0, -4.0), &Vec3::y()),
+      projection_matrix: perspective(1.0, 1.2, 0.1, 100.0),
+      viewport_matrix: viewport(128.0, 128.0),
+      time: 0.0,
+      noise: &noise,
+      lights: &[],
+      camera_position: eye,
+      shadow_map: None,
+    };
+    let corners = [Vec3::new(-2.0, -1.0, 0.0), Vec3::new(2.0, -1.0, 0.0), Vec3::new(0.0, 8.0, 0.0)]
+      .map(|position| vertex_shader(&Vertex::new(position, Vec3::z(), nalgebra_glm::Vec2::zeros()), &uniforms));
+
+    let clip = Rect::new(0, 0, 128, 128);
+    let to_screen = uniforms.viewport_matrix * uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix;
+    triangle(&corners[0], &corners[1], &corners[2], &clip)
+      .iter()
+      .map(|fragment| {
+        let p = fragment.vertex_position;
+        let screen = to_screen * Vec4::new(p.x, p.y, p.z, 1.0);
+        let (x, y) = (screen.x / screen.w, screen.y / screen.w);
+        ((x - fragment.position.x - 0.5).powi(2) + (y - fragment.position.y - 0.5).powi(2)).sqrt()
+      })
+      .collect()
+  }
+
+  // Triángulo que va de delante de la cámara hasta detrás de ella
+  fn crossing_triangle() -> [Vertex; 3] {
+    [Vec3::new(-1.0, -1.0, -5.0), Vec3::new(1.0, -1.0, -5.0), Vec3::new(0.0, 1.0, 5.0)]
+      .map(|position| Vertex::new(position, Vec3::z(), nalgebra_glm::Vec2::zeros()))
+  }
+
+  #[test]
+  fn triangles_crossing_the_near_plane_are_cut_not_dropped() {
+    let clip_matrix = perspective(1.0, 1.2, 0.1, 100.0);
+    let [a, b, c] = crossing_triangle();
+    let pieces = clip_triangle([&a, &b, &c], &clip_matrix);
+    assert!(!pieces.is_empty(), "la parte visible debe sobrevivir");
+    for vertex in pieces.iter().flatten() {
+      let clip = clip_matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+      assert!(clip.w > 0.0 && (clip.z / clip.w).abs() <= 1.0 + 1e-4, "vértice fuera del volumen: {:?}", clip);
+    }
+
+    // El área visible no cambia: los pedazos cubren lo que queda delante de z = -0.1, fuera de la punta de altura 5.1 de 10
+    let area = |[a, b, c]: &[Vertex; 3]| (b.position - a.position).cross(&(c.position - a.position)).magnitude() * 0.5;
+    let visible = pieces.iter().map(area).sum::<f32>();
+    let expected = area(&[a.clone(), b.clone(), c.clone()]) * (1.0 - 0.51f32.powi(2));
+    assert!((visible - expected).abs() < 1e-3, "{} != {}", visible, expected);
+  }
+
+  #[test]
+  fn triangles_fully_inside_or_outside_are_kept_or_dropped_whole() {
+    let clip_matrix = perspective(1.0, 1.2, 0.1, 100.0);
+    let inside = [Vec3::new(-1.0, -1.0, -5.0), Vec3::new(1.0, -1.0, -5.0), Vec3::new(0.0, 1.0, -6.0)]
+      .map(|position| Vertex::new(position, Vec3::z(), nalgebra_glm::Vec2::zeros()));
+    let kept = clip_triangle([&inside[0], &inside[1], &inside[2]], &clip_matrix);
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0][2].position, inside[2].position);
+
+    let behind = inside.clone().map(|vertex| Vertex::new(-vertex.position, Vec3::z(), nalgebra_glm::Vec2::zeros()));
+    assert!(clip_triangle([&behind[0], &behind[1], &behind[2]], &clip_matrix).is_empty());
+  }
+
+  #[test]
+  fn bounding_boxes_are_clamped_to_the_clip_rect() {
+    let clip = Rect::new(0, 0, 800, 600);
+
+    // Parcialmente fuera por la izquierda y por abajo: se recorta al borde
+    assert_eq!(clip_bounding_box((-50, 500, 120, 900), &clip), Some((0, 500, 120, 599)));
+    // Más grande que la pantalla en todas direcciones: queda la pantalla completa
+    assert_eq!(clip_bounding_box((-5000, -5000, 5000, 5000), &clip), Some((0, 0, 799, 599)));
+    // Completamente fuera, a cualquier lado
+    assert_eq!(clip_bounding_box((-300, 10, -1, 40), &clip), None);
+    assert_eq!(clip_bounding_box((10, 600, 40, 700), &clip), None);
+
+    // Con scissor solo queda la parte dentro del panel
+    let panel = Rect::new(400, 300, 200, 100);
+    assert_eq!(clip_bounding_box((350, 250, 450, 350), &panel), Some((400, 300, 450, 350)));
+    assert_eq!(clip_bounding_box((0, 0, 399, 299), &panel), None);
+    assert_eq!(clip_bounding_box((0, 0, 10, 10), &Rect::new(0, 0, 0, 0)), None);
+  }
+
+  #[test]
+  fn object_position_stays_under_its_pixel_from_any_camera() {
+    for eye in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.5, 0.8, 0.5), Vec3::new(-1.0, -0.5, 2.5)] {
+      let errors = reprojection_errors(eye);
+      assert!(errors.len() > 100, "el triángulo debe cubrir la vista");
+      let worst = errors.iter().cloned().fold(0.0, f32::max);
+      assert!(worst < 0.05, "desplazamiento de {} px desde {:?}", worst, eye);
+    }
+  }
+}
@@ -1,29 +1,205 @@
 use nalgebra_glm::{Vec3, Vec4, Mat4};
+use crate::vertex::Vertex;
 
+// Planos del frustum en espacio de mundo, con la normal apuntando hacia adentro
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Extrae los planos de la matriz vista-proyección (método de Gribb-Hartmann)
+    pub fn from_matrix(view_projection: &Mat4) -> Self {
+        let m = view_projection;
+        let row = |i: usize| Vec4::new(m[(i, 0)], m[(i, 1)], m[(i, 2)], m[(i, 3)]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let planes = [
+            r3 + r0, // izquierda
+            r3 - r0, // derecha
+            r3 + r1, // abajo
+            r3 - r1, // arriba
+            r3 + r2, // cerca
+            r3 - r2, // lejos
+        ]
+        .map(|plane| {
+            let length = plane.xyz().magnitude();
+            if length > f32::EPSILON { plane / length } else { plane }
+        });
+
+        Frustum { planes }
+    }
+
+    pub fn new(view_matrix: &Mat4, projection_matrix: &Mat4) -> Self {
+        Frustum::from_matrix(&(projection_matrix * view_matrix))
+    }
+
+    // La esfera se descarta solo si queda completamente detrás de algún plano
+    pub fn intersects_sphere(&self, center: &Vec3, radius: f32) -> bool {
+        self.planes.iter().all(|plane| {
+            plane.xyz().dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+// Punto del mundo bajo el centro del pixel a la profundidad NDC dada
+pub fn unproject(inverse_view_projection: &Mat4, x: usize, y: usize, width: usize, height: usize, ndc_z: f32) -> Vec3 {
+    let ndc_x = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+    let ndc_y = 1.0 - (y as f32 + 0.5) / height as f32 * 2.0;
+    let world = inverse_view_projection * Vec4::new(ndc_x, ndc_y, ndc_z, 1.0);
+    world.xyz() / world.w
+}
+
+// Distancias de entrada y salida de un rayo (dirección unitaria) en una esfera
+pub fn ray_sphere(origin: &Vec3, direction: &Vec3, center: &Vec3, radius: f32) -> Option<(f32, f32)> {
+    let offset = origin - center;
+    let b = offset.dot(direction);
+    let c = offset.magnitude_squared() - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some((-b - root, -b + root))
+}
+
+// Dirección (sin normalizar) de la coordenada (u, v) en [-1, 1] de una cara del cubo
+pub fn cube_face_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+}
+
+// Cara y coordenadas (u, v) en [-1, 1] para una dirección
+pub fn cube_locate(direction: &Vec3) -> (usize, f32, f32) {
+    let a = direction.abs();
+    if a.x >= a.y && a.x >= a.z {
+        if direction.x > 0.0 {
+            (0, -direction.z / a.x, -direction.y / a.x)
+        } else {
+            (1, direction.z / a.x, -direction.y / a.x)
+        }
+    } else if a.y >= a.z {
+        if direction.y > 0.0 {
+            (2, direction.x / a.y, direction.z / a.y)
+        } else {
+            (3, direction.x / a.y, -direction.z / a.y)
+        }
+    } else if direction.z > 0.0 {
+        (4, direction.x / a.z, -direction.y / a.z)
+    } else {
+        (5, -direction.x / a.z, -direction.y / a.z)
+    }
+}
+
+// Tangente y bitangente unitarias cualesquiera, perpendiculares a la normal
+pub fn orthonormal_basis(normal: &Vec3) -> (Vec3, Vec3) {
+    if normal.magnitude_squared() <= f32::EPSILON {
+        return (Vec3::x(), Vec3::z());
+    }
+    let normal = normal.normalize();
+    let helper = if normal.x.abs() < 0.9 { Vec3::x() } else { Vec3::y() };
+    let tangent = normal.cross(&helper).normalize();
+    (tangent, normal.cross(&tangent))
+}
+
+// Gram-Schmidt de la base tangente contra la normal; la bitangente conserva su
+// lado para respetar el espejado de las coordenadas de textura
+pub fn orthonormalize_frame(normal: &Vec3, tangent: &Vec3, bitangent: &Vec3) -> (Vec3, Vec3) {
+    if normal.magnitude_squared() <= f32::EPSILON {
+        return orthonormal_basis(normal);
+    }
+    let normal = normal.normalize();
+    let tangent = tangent - normal * normal.dot(tangent);
+    if tangent.magnitude_squared() <= 1e-12 {
+        return orthonormal_basis(&normal);
+    }
+    let tangent = tangent.normalize();
+    let side = normal.cross(&tangent);
+    (tangent, if side.dot(bitangent) < 0.0 { -side } else { side })
+}
+
+// Interpolación de Hermite entre dos bordes, 0 antes de edge0 y 1 después de edge1
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Radio de la esfera que envuelve la malla, medido desde el origen del objeto
+pub fn bounding_radius(vertices: &[Vertex]) -> f32 {
+    vertices.iter()
+        .map(|vertex| vertex.position.magnitude())
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use nalgebra_glm::{look_at, perspective};
+    use std::f32::consts::PI;
 
+    // Direcciones unitarias deterministas repartidas sobre la esfera, para las
+    // pruebas que recorren la superficie de un planeta
+    pub fn sphere_samples(n: usize) -> Vec<Vec3> {
+        (0..n)
+            .map(|i| {
+                let t = i as f32;
+                Vec3::new((t * 0.37).sin(), (t * 0.11).cos(), (t * 0.23).sin()).normalize()
+            })
+            .collect()
+    }
+
+    fn test_frustum() -> Frustum {
+        let view = look_at(
+            &Vec3::new(0.0, 0.0, 10.0),
+            &Vec3::new(0.0, 0.0, 0.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+        let projection = perspective(1.0, PI / 2.0, 0.1, 100.0);
+        Frustum::new(&view, &projection)
+    }
 
-pub fn frustum_culling(
-    position: &Vec3,
-    scale: f32,
-    view_matrix: &Mat4,
-    projection_matrix: &Mat4,
-) -> bool {
-    let world_pos = Vec4::new(position.x, position.y, position.z, 1.0);
-    let clip_space_pos = projection_matrix * view_matrix * world_pos;
-    let margin = scale * 1.5;
+    #[test]
+    fn sphere_in_front_of_camera_is_visible() {
+        assert!(test_frustum().intersects_sphere(&Vec3::zeros(), 1.0));
+    }
+
+    #[test]
+    fn sphere_behind_camera_is_culled() {
+        assert!(!test_frustum().intersects_sphere(&Vec3::new(0.0, 0.0, 20.0), 1.0));
+    }
 
-    // Dividir por w para obtener coordenadas NDC (Normalized Device Coordinates)
-    let w = clip_space_pos.w;
-    if w.abs() < f32::EPSILON {
-        return false; // Prevenir divisiones por cero
+    #[test]
+    fn sphere_beyond_far_plane_is_culled() {
+        assert!(!test_frustum().intersects_sphere(&Vec3::new(0.0, 0.0, -95.0), 1.0));
+        assert!(test_frustum().intersects_sphere(&Vec3::new(0.0, 0.0, -95.0), 10.0));
     }
-    let ndc_x = clip_space_pos.x / w;
-    let ndc_y = clip_space_pos.y / w;
-    let ndc_z = clip_space_pos.z / w;
 
-    // Verificar si está dentro del frustum considerando el margen
-    ndc_x.abs() <= 1.0 + margin
-        && ndc_y.abs() <= 1.0 + margin
-        && ndc_z >= -1.0 - margin
-        && ndc_z <= 1.0 + margin
+    #[test]
+    fn large_sphere_with_center_outside_is_visible() {
+        // Con fov de 90° el borde izquierdo pasa por x = -10 a esta distancia
+        let center = Vec3::new(-11.0, 0.0, 0.0);
+        assert!(test_frustum().intersects_sphere(&center, 2.0));
+        assert!(!test_frustum().intersects_sphere(&center, 0.5));
+    }
+
+    #[test]
+    fn sphere_touching_near_plane_is_visible() {
+        assert!(test_frustum().intersects_sphere(&Vec3::new(0.0, 0.0, 10.5), 0.7));
+        assert!(!test_frustum().intersects_sphere(&Vec3::new(0.0, 0.0, 10.5), 0.3));
+    }
+
+    #[test]
+    fn bounding_radius_uses_farthest_vertex() {
+        let vertices = vec![
+            Vertex::new(Vec3::new(0.5, 0.0, 0.0), Vec3::y(), Default::default()),
+            Vertex::new(Vec3::new(0.0, -2.0, 0.0), Vec3::y(), Default::default()),
+        ];
+        assert_eq!(bounding_radius(&vertices), 2.0);
+        assert_eq!(bounding_radius(&[]), 0.0);
+    }
 }
@@ -1,25 +1,42 @@
 use nalgebra_glm::{Vec2, Vec3};
 use crate::color::Color;
+use crate::utils::{orthonormal_basis, orthonormalize_frame};
 
 #[derive(Clone, Debug)]
 pub struct Vertex {
   pub position: Vec3,
   pub normal: Vec3,
+  // Base tangente en espacio de objeto: hacia donde crecen u y v sobre la superficie
+  pub tangent: Vec3,
+  pub bitangent: Vec3,
   pub tex_coords: Vec2,
   pub color: Color,
   pub transformed_position: Vec3,
   pub transformed_normal: Vec3,
+  pub transformed_tangent: Vec3,
+  pub transformed_bitangent: Vec3,
+  pub world_position: Vec3,
+  // 1/w del espacio de recorte, para interpolar con corrección de perspectiva
+  pub inverse_w: f32,
 }
 
 impl Vertex {
+  // Sin coordenadas de textura útiles la base tangente es una cualquiera alrededor de la normal
   pub fn new(position: Vec3, normal: Vec3, tex_coords: Vec2) -> Self {
+    let (tangent, bitangent) = orthonormal_basis(&normal);
     Vertex {
       position,
       normal,
+      tangent,
+      bitangent,
       tex_coords,
       color: Color::black(),
       transformed_position: position,
       transformed_normal: normal,
+      transformed_tangent: tangent,
+      transformed_bitangent: bitangent,
+      world_position: position,
+      inverse_w: 1.0,
     }
   }
 
@@ -27,10 +44,39 @@ impl Vertex {
     Vertex {
       position,
       normal: Vec3::new(0.0, 0.0, 0.0),
+      tangent: Vec3::new(0.0, 0.0, 0.0),
+      bitangent: Vec3::new(0.0, 0.0, 0.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color,
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 0.0, 0.0),
+      transformed_tangent: Vec3::new(0.0, 0.0, 0.0),
+      transformed_bitangent: Vec3::new(0.0, 0.0, 0.0),
+      world_position: position,
+      inverse_w: 1.0,
+    }
+  }
+
+  // Ajusta la base tangente a la normal del vértice
+  pub fn with_tangents(mut self, tangent: Vec3, bitangent: Vec3) -> Self {
+    let (tangent, bitangent) = orthonormalize_frame(&self.normal, &tangent, &bitangent);
+    self.tangent = tangent;
+    self.bitangent = bitangent;
+    self.transformed_tangent = tangent;
+    self.transformed_bitangent = bitangent;
+    self
+  }
+
+  // Punto sobre la arista hacia other; solo los atributos de objeto, el resto lo recalcula el vertex shader
+  pub fn lerp(&self, other: &Vertex, t: f32) -> Self {
+    Vertex {
+      position: self.position.lerp(&other.position, t),
+      normal: self.normal.lerp(&other.normal, t),
+      tangent: self.tangent.lerp(&other.tangent, t),
+      bitangent: self.bitangent.lerp(&other.bitangent, t),
+      tex_coords: self.tex_coords.lerp(&other.tex_coords, t),
+      color: self.color.lerp(&other.color, t),
+      ..Vertex::default()
     }
   }
 
@@ -45,10 +91,16 @@ impl Default for Vertex {
     Vertex {
       position: Vec3::new(0.0, 0.0, 0.0),
       normal: Vec3::new(0.0, 1.0, 0.0),
+      tangent: Vec3::new(1.0, 0.0, 0.0),
+      bitangent: Vec3::new(0.0, 0.0, -1.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color: Color::black(),
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 1.0, 0.0),
+      transformed_tangent: Vec3::new(1.0, 0.0, 0.0),
+      transformed_bitangent: Vec3::new(0.0, 0.0, -1.0),
+      world_position: Vec3::new(0.0, 0.0, 0.0),
+      inverse_w: 1.0,
     }
   }
 }